@@ -0,0 +1,392 @@
+//! The command line arguments, which also configure the library.
+
+use std::{num::NonZeroUsize, path::PathBuf};
+
+use clap::{Parser, ValueEnum};
+
+use crate::{Format, LineEnding, Reader};
+
+/// CLI arguments struct using [`clap`]'s Derive API.
+///
+/// The library takes these too, so other programs can set up a run the same
+/// way the binary does, for example with [`Args::parse_from`].
+#[derive(Debug, Parser)]
+#[clap(about, author, version)]
+pub struct Args {
+  /// Exit with an error when a user's games can't be scanned, instead of
+  /// skipping them.
+  #[clap(long)]
+  pub abort_on_private: bool,
+
+  /// Leave out feeds without any news from the last N days, implies
+  /// `--verify`.
+  #[clap(long, value_name = "DAYS")]
+  pub active_within: Option<u64>,
+
+  /// A game's AppID, can be used multiple times.
+  #[clap(short, long)]
+  pub appid: Vec<usize>,
+
+  /// Add a unique query parameter to every verification request, so caches
+  /// between Steam and you can't serve stale feeds.
+  ///
+  /// The parameter is never included in the output feed URLs.
+  #[clap(long)]
+  pub cache_bust: bool,
+
+  /// While verifying, write the feeds verified so far to the `--output` file
+  /// every N feeds.
+  #[clap(long, requires = "output", value_name = "N")]
+  pub checkpoint_every: Option<NonZeroUsize>,
+
+  /// A Steam session cookie like `steamLoginSecure=...` to send to
+  /// steamcommunity.com, for scanning your own private profile.
+  #[clap(long, conflicts_with = "cookie_file")]
+  pub cookie: Option<String>,
+
+  /// A file containing a Steam session cookie, see `--cookie`.
+  #[clap(long, value_name = "PATH")]
+  pub cookie_file: Option<PathBuf>,
+
+  /// Print how many feeds every scanned user contributed to stderr.
+  #[clap(long)]
+  pub count_by_user: bool,
+
+  /// Output a JSON object with the feed URLs that were `added` and `removed`
+  /// compared to a baseline JSON or OPML file from an earlier run, instead of
+  /// the feeds themselves.
+  #[clap(long, value_name = "BASELINE")]
+  pub diff_json: Option<PathBuf>,
+
+  /// Save every raw response body to this directory, named by a hash of the
+  /// URL and listed in `urls.txt`.
+  ///
+  /// Note that these can include profile data, like a user's games.
+  #[clap(long, value_name = "DIR")]
+  pub dump_raw: Option<PathBuf>,
+
+  /// Include game icons in the `--format html` output, fetching them from the
+  /// store for games that weren't found by scanning a user.
+  #[clap(long)]
+  pub fetch_icons: bool,
+
+  /// The format to output the feeds as.
+  #[clap(long, value_enum, default_value = "urls")]
+  pub format: Format,
+
+  /// Put the feeds of every scanned user in their own OPML folder, named
+  /// with this template.
+  ///
+  /// `{user}` is the vanity User ID, `{name}` the display name and
+  /// `{steamid}` the SteamID64. Unknown values fall back to the User ID, or
+  /// the SteamID64 for profiles without one.
+  #[clap(long, value_name = "TEMPLATE")]
+  pub group_name_template: Option<String>,
+
+  /// A JSON file to remember the valid feed URL of every verified game in,
+  /// to warn when Steam moves a feed between its AppID and friendly URL.
+  #[clap(long, value_name = "PATH")]
+  pub history_file: Option<PathBuf>,
+
+  /// An extra HTTP header to send with every request, in the form of
+  /// `Name: Value`, can be used multiple times.
+  #[clap(long, value_name = "HEADER", value_parser = parse_http_header)]
+  pub http_header: Vec<(String, String)>,
+
+  /// Collapse duplicate AppIDs, URLs and users before resolving them.
+  #[clap(long)]
+  pub input_dedupe: bool,
+
+  /// Ask whether a `--user` that could be both a SteamID64 and a vanity User
+  /// ID is meant as one or the other, which needs the `dialoguer` feature and
+  /// a terminal. Otherwise they're always treated as SteamID64s.
+  #[clap(long)]
+  pub interactive_resolve: bool,
+
+  /// The line ending to use for the output, scripts always use `lf`.
+  #[clap(long, value_enum, default_value = "lf")]
+  pub line_ending: LineEnding,
+
+  /// Print all supported output formats and exit.
+  #[clap(long)]
+  pub list_formats: bool,
+
+  /// Exit with an error when a response body is larger than this many bytes.
+  #[clap(long, value_name = "BYTES")]
+  pub max_body_size: Option<u64>,
+
+  /// The maximum amount of user game pages to fetch at the same time.
+  #[clap(long, default_value = "1", value_name = "N")]
+  pub max_concurrent_users: NonZeroUsize,
+
+  /// Stop once this many user scans or feed verifications have failed, and
+  /// output the feeds found until then.
+  #[clap(long, value_name = "N")]
+  pub max_failures: Option<NonZeroUsize>,
+
+  /// The maximum amount of friends to scan with `--users-from-friends`.
+  #[clap(long, requires = "users_from_friends", value_name = "N")]
+  pub max_friends: Option<usize>,
+
+  /// Exit with an error when less than this percentage of the verified feeds
+  /// were valid, implies `--verify`.
+  #[clap(
+    long,
+    value_name = "PERCENT",
+    value_parser = clap::value_parser!(u8).range(0..=100)
+  )]
+  pub min_success_rate: Option<u8>,
+
+  /// Exit with an error instead of overwriting the `--output` file when it
+  /// already exists.
+  #[clap(long, requires = "output", conflicts_with = "output_overwrite")]
+  pub no_clobber: bool,
+
+  /// Only output feeds that were successfully verified, implies `--verify`.
+  ///
+  /// Exits with an error if any unverified feed would be output.
+  #[clap(long)]
+  pub only_verified: bool,
+
+  /// Output the feeds as OPML, shorthand for `--format opml`.
+  #[clap(long, conflicts_with = "format")]
+  pub opml: bool,
+
+  /// Where to get the text of every feed from, the other sources are used as
+  /// fallbacks when it isn't available.
+  #[clap(long, value_enum, default_value = "verified")]
+  pub opml_text_from: TextSource,
+
+  /// Write the output to a file instead of stdout.
+  #[clap(short, long, value_name = "PATH")]
+  pub output: Option<PathBuf>,
+
+  /// Overwrite the `--output` file when it already exists, this is the
+  /// default.
+  #[clap(long, requires = "output")]
+  pub output_overwrite: bool,
+
+  /// Which feed URL to use for games, when verifying the other one is tried
+  /// if the preferred one isn't valid. Without this only community feeds are
+  /// used.
+  #[clap(long, value_enum)]
+  pub prefer: Option<FeedSource>,
+
+  /// The feed reader to generate the `--format script` output for.
+  #[clap(long, value_enum, default_value = "newsboat")]
+  pub reader: Reader,
+
+  /// A file with `AppID=Name` or `URL=Name` lines, to override the text of
+  /// those feeds.
+  #[clap(long, value_name = "PATH")]
+  pub rename_map: Option<PathBuf>,
+
+  /// When verifying, look up the friendly URL of games from AppIDs and store
+  /// URLs when their AppID feed isn't valid.
+  #[clap(long)]
+  pub resolve_friendly_url: bool,
+
+  /// When verifying, try the game's friendly URL when the title of its feed
+  /// doesn't match the game's name, in case Steam served the wrong feed.
+  #[clap(long)]
+  pub revalidate_on_mismatch: bool,
+
+  /// Abandon a user's game page when fetching it takes longer than this many
+  /// milliseconds.
+  #[clap(long, value_name = "MS")]
+  pub scan_timeout: Option<u64>,
+
+  /// Write every input that didn't result in a feed to this file as JSON,
+  /// with the reason it was skipped.
+  #[clap(long, value_name = "PATH")]
+  pub skipped_file: Option<PathBuf>,
+
+  /// Read a JSON array of inputs from stdin, like
+  /// `[{"kind": "user", "value": "Bauke"}, {"kind": "appid", "value": 440}]`.
+  ///
+  /// The kinds are `appid`, `url` and `user`, the same as the options with
+  /// those names.
+  #[clap(long)]
+  pub stdin_json: bool,
+
+  /// A Steam Web API key from https://steamcommunity.com/dev/apikey, needed
+  /// for `--users-from-friends`.
+  #[clap(long, value_name = "KEY")]
+  pub steam_api_key: Option<String>,
+
+  /// Remove BBCode tags like `[b]` and `[url=...]` from the feed texts.
+  #[clap(long)]
+  pub strip_bbcode: bool,
+
+  /// Render the feeds with a TinyTemplate file instead of using `--format`,
+  /// see the README for what's available in it.
+  #[clap(long, value_name = "PATH")]
+  pub template_file: Option<PathBuf>,
+
+  /// The time in milliseconds to sleep between HTTP requests.
+  #[clap(short, long, default_value = "250")]
+  pub timeout: u64,
+
+  /// A game's store URL, can be used multiple times.
+  #[clap(long)]
+  pub url: Vec<String>,
+
+  /// A person's steamcommunity.com ID or full URL, can be used multiple times.
+  #[clap(long)]
+  pub user: Vec<String>,
+
+  /// Text to add to the end of the User-Agent, like a way to contact you.
+  #[clap(long, value_name = "STR", value_parser = parse_user_agent_append)]
+  pub user_agent_append: Option<String>,
+
+  /// Scan the games of a person's friends, using their SteamID64 or
+  /// steamcommunity.com ID or URL.
+  ///
+  /// The friends are put in their own OPML folders, named with
+  /// `--group-name-template` or their display name. Friends with a private
+  /// profile are skipped.
+  #[clap(long, requires = "steam_api_key", value_name = "USER")]
+  pub users_from_friends: Option<String>,
+
+  /// Check that the OPML output can be parsed again and still contains every
+  /// feed before writing it.
+  #[clap(long)]
+  pub validate_opml: bool,
+
+  /// Verify potential feeds by downloading them and checking if they return XML.
+  #[clap(short, long)]
+  pub verify: bool,
+
+  /// Use a verified feed's title even when it's empty, instead of falling
+  /// back to the game's name or AppID.
+  #[clap(long)]
+  pub verify_accept_empty_title: bool,
+
+  /// Check feeds with a HEAD request first and only download the ones that
+  /// look valid.
+  ///
+  /// Age checks can then only be detected when Steam redirects to one, an
+  /// age check served in place of the feed is reported as an invalid feed
+  /// instead of being retried.
+  #[clap(long)]
+  pub verify_head_then_get: bool,
+
+  /// Only verify the first N feeds and output the rest without verifying
+  /// them, for a quick check that is faster than `--verify`.
+  ///
+  /// Invalid feeds in the sample are left out like with `--verify`, and the
+  /// JSON and NDJSON output mark which feeds were verified.
+  #[clap(long, value_name = "N", conflicts_with = "only_verified")]
+  pub verify_sample: Option<usize>,
+
+  /// Warn about feeds that take longer than this many milliseconds to verify.
+  #[clap(long, value_name = "MS")]
+  pub warn_slow_feeds: Option<u64>,
+}
+
+impl Args {
+  /// Whether the feeds are verified, which some other options imply.
+  pub fn verifies(&self) -> bool {
+    self.verify
+      || self.only_verified
+      || self.verify_sample.is_some()
+      || self.min_success_rate.is_some()
+      || self.active_within.is_some()
+  }
+}
+
+/// The sources that the text of a feed can come from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum TextSource {
+  /// The title of the verified feed.
+  Verified,
+
+  /// The name of the game from a user's games.
+  Scraped,
+
+  /// A generic label with the game's AppID.
+  Appid,
+}
+
+/// The places that Steam serves RSS feeds for games from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum FeedSource {
+  /// `https://steamcommunity.com/games/<AppID>/rss/`
+  Community,
+
+  /// `https://store.steampowered.com/feeds/news/app/<AppID>/`
+  Store,
+}
+
+/// Checks the text for `--user-agent-append`, which has to be printable
+/// ASCII so it's a valid part of the header.
+pub fn parse_user_agent_append(append: &str) -> Result<String, String> {
+  let append = append.trim();
+  if append.is_empty() {
+    return Err("the text can't be empty".to_string());
+  }
+
+  if !append.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+    return Err(format!(
+      "\"{append}\" can only contain printable ASCII characters"
+    ));
+  }
+
+  Ok(append.to_string())
+}
+
+/// Parses a `Name: Value` HTTP header given on the command line.
+pub fn parse_http_header(header: &str) -> Result<(String, String), String> {
+  let Some((name, value)) = header.split_once(':') else {
+    return Err(format!("expected \"Name: Value\" but got \"{header}\""));
+  };
+
+  // Header names are RFC 9110 tokens.
+  let is_token_char =
+    |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+  let name = name.trim();
+  if name.is_empty() || !name.chars().all(is_token_char) {
+    return Err(format!("\"{name}\" isn't a valid header name"));
+  }
+
+  let value = value.trim();
+  if value.chars().any(|c| c.is_ascii_control() && c != '\t') {
+    return Err(format!(
+      "the value for \"{name}\" contains control characters"
+    ));
+  }
+
+  Ok((name.to_string(), value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn http_headers_are_parsed() {
+    assert_eq!(
+      parse_http_header("Accept-Language:  en-US "),
+      Ok(("Accept-Language".to_string(), "en-US".to_string()))
+    );
+    assert_eq!(
+      parse_http_header("X-Empty:"),
+      Ok(("X-Empty".to_string(), String::new()))
+    );
+    assert!(parse_http_header("No colon").is_err());
+    assert!(parse_http_header(": value").is_err());
+    assert!(parse_http_header("Bad Name: value").is_err());
+    assert!(parse_http_header("Name: line\nbreak").is_err());
+  }
+
+  #[test]
+  fn user_agent_appends_are_checked() {
+    assert_eq!(
+      parse_user_agent_append(" (contact: me@example.com) "),
+      Ok("(contact: me@example.com)".to_string())
+    );
+    assert!(parse_user_agent_append("  ").is_err());
+    assert!(parse_user_agent_append("tab\there").is_err());
+    assert!(parse_user_agent_append("naïve").is_err());
+  }
+}
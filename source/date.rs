@@ -0,0 +1,194 @@
+//! Small helpers for the dates found in RSS feeds.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Parses an RFC 2822 date, like `Tue, 10 Oct 2023 17:12:03 +0000`, into
+/// seconds since the Unix epoch.
+///
+/// Only the parts RSS feeds actually use are supported: an optional weekday,
+/// the date, a time with optional seconds and either a numeric offset or one
+/// of the common named zones.
+pub fn parse_rfc2822(date: &str) -> Option<i64> {
+  let mut parts = date.split_whitespace().peekable();
+  if parts.peek()?.ends_with(',') {
+    parts.next();
+  }
+
+  let day = parse_in_range(parts.next()?, 1..=31)?;
+  let month = match parts.next()?.to_ascii_lowercase().as_str() {
+    "jan" => 1,
+    "feb" => 2,
+    "mar" => 3,
+    "apr" => 4,
+    "may" => 5,
+    "jun" => 6,
+    "jul" => 7,
+    "aug" => 8,
+    "sep" => 9,
+    "oct" => 10,
+    "nov" => 11,
+    "dec" => 12,
+    _ => return None,
+  };
+  let year = parse_in_range(parts.next()?, 0..=9999)?;
+
+  let mut time = parts.next()?.split(':');
+  let hours = parse_in_range(time.next()?, 0..=23)?;
+  let minutes = parse_in_range(time.next()?, 0..=59)?;
+  // Leap seconds are written as `:60`.
+  let seconds = time.next().map_or(Some(0), |s| parse_in_range(s, 0..=60))?;
+
+  let offset = match parts.next().unwrap_or("+0000") {
+    "UT" | "UTC" | "GMT" | "Z" => 0,
+    "EDT" => -4 * 3600,
+    "EST" | "CDT" => -5 * 3600,
+    "CST" | "MDT" => -6 * 3600,
+    "MST" | "PDT" => -7 * 3600,
+    "PST" => -8 * 3600,
+    zone => {
+      let (sign, digits) = if let Some(digits) = zone.strip_prefix('+') {
+        (1, digits)
+      } else if let Some(digits) = zone.strip_prefix('-') {
+        (-1, digits)
+      } else {
+        return None;
+      };
+
+      // Only ASCII digits can be sliced by byte index safely.
+      if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+      }
+
+      let offset_hours = digits[..2].parse::<i64>().ok()?;
+      let offset_minutes = digits[2..].parse::<i64>().ok()?;
+      sign * (offset_hours * 3600 + offset_minutes * 60)
+    }
+  };
+
+  let days = days_from_civil(year, month, day);
+  Some(days * 86400 + hours * 3600 + minutes * 60 + seconds - offset)
+}
+
+/// Parses a number that has to be within `range`, so out of range dates are
+/// rejected instead of overflowing when they're converted.
+fn parse_in_range(
+  number: &str,
+  range: std::ops::RangeInclusive<i64>,
+) -> Option<i64> {
+  number.parse().ok().filter(|number| range.contains(number))
+}
+
+/// Returns the number of days between the Unix epoch and a date, using
+/// Howard Hinnant's `days_from_civil` algorithm.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year - era * 400;
+  let month_index = (month + 9) % 12;
+  let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+  let day_of_era =
+    year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  era * 146097 + day_of_era - 719468
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+pub fn now() -> i64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |duration| duration.as_secs() as i64)
+}
+
+/// Formats the time between a Unix timestamp and `now` as a human-readable
+/// age, like `3 days ago`.
+pub fn format_age(timestamp: i64, now: i64) -> String {
+  let seconds = (now - timestamp).max(0);
+  let (amount, unit) = match seconds {
+    0..=59 => return "just now".to_string(),
+    60..=3599 => (seconds / 60, "minute"),
+    3600..=86399 => (seconds / 3600, "hour"),
+    _ => (seconds / 86400, "day"),
+  };
+
+  let plural = if amount == 1 { "" } else { "s" };
+  format!("{amount} {unit}{plural} ago")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// `Tue, 10 Oct 2023 17:12:03 +0000` in seconds since the Unix epoch.
+  const TIMESTAMP: i64 = 1_696_957_923;
+
+  #[test]
+  fn parses_numeric_offsets() {
+    for date in [
+      "Tue, 10 Oct 2023 17:12:03 +0000",
+      "Tue, 10 Oct 2023 19:12:03 +0200",
+      "Tue, 10 Oct 2023 12:42:03 -0430",
+    ] {
+      assert_eq!(parse_rfc2822(date), Some(TIMESTAMP), "{date}");
+    }
+  }
+
+  #[test]
+  fn parses_named_zones() {
+    for date in [
+      "Tue, 10 Oct 2023 17:12:03 GMT",
+      "Tue, 10 Oct 2023 17:12:03 UTC",
+      "Tue, 10 Oct 2023 13:12:03 EDT",
+      "Tue, 10 Oct 2023 09:12:03 PST",
+    ] {
+      assert_eq!(parse_rfc2822(date), Some(TIMESTAMP), "{date}");
+    }
+  }
+
+  #[test]
+  fn parses_optional_parts() {
+    assert_eq!(parse_rfc2822("10 Oct 2023 17:12:03"), Some(TIMESTAMP));
+    assert_eq!(
+      parse_rfc2822("10 oct 2023 17:12 +0000"),
+      Some(TIMESTAMP - 3)
+    );
+  }
+
+  #[test]
+  fn rejects_invalid_dates() {
+    for date in [
+      "",
+      "Tue, 10 Foo 2023 17:12:03 +0000",
+      "Tue, 10 Oct 2023 17:12:03 +02",
+      "Tue, 10 Oct 2023 17:12:03 CEST",
+      "Tue, 10 Oct 2023",
+      "Tue, 10 Oct 2023 17:12:03 ÉST",
+      "Tue, 10 Oct 2023 17:12:03 +ÉST",
+      "Tue, 10 Oct 2023 17:12:03 +0é0",
+      "Tue, 10 Oct 99999999999999999 17:12:03 +0000",
+      "Tue, 10 Oct 2023 24:00:00 +0000",
+      "Tue, 10 Oct 2023 17:60:00 +0000",
+      "Tue, 10 Oct 2023 17:12:61 +0000",
+      "Tue, 32 Oct 2023 17:12:03 +0000",
+    ] {
+      assert_eq!(parse_rfc2822(date), None, "{date}");
+    }
+  }
+
+  #[test]
+  fn counts_days_from_the_epoch() {
+    assert_eq!(days_from_civil(1970, 1, 1), 0);
+    assert_eq!(days_from_civil(1969, 12, 31), -1);
+    assert_eq!(days_from_civil(2000, 3, 1), 11017);
+    assert_eq!(days_from_civil(2024, 2, 29), 19782);
+  }
+
+  #[test]
+  fn formats_ages() {
+    let now = TIMESTAMP;
+    assert_eq!(format_age(now + 60, now), "just now");
+    assert_eq!(format_age(now - 59, now), "just now");
+    assert_eq!(format_age(now - 60, now), "1 minute ago");
+    assert_eq!(format_age(now - 7200, now), "2 hours ago");
+    assert_eq!(format_age(now - 86400, now), "1 day ago");
+    assert_eq!(format_age(now - 3 * 86400 - 5, now), "3 days ago");
+  }
+}
@@ -0,0 +1,162 @@
+//! Comparing feeds with the ones from an earlier run, for `--diff-json`.
+
+use std::collections::HashSet;
+
+use {
+  color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+  },
+  serde::Serialize,
+  serde_json::Value,
+};
+
+use crate::Feed;
+
+/// The feed URLs that changed compared to a `--diff-json` baseline.
+#[derive(Debug, Serialize)]
+pub struct FeedDiff {
+  /// The URLs that aren't in the baseline.
+  pub added: Vec<String>,
+
+  /// The URLs from the baseline that weren't found anymore.
+  pub removed: Vec<String>,
+}
+
+impl FeedDiff {
+  /// Compares the feeds with the URLs from a baseline. URLs are compared
+  /// after [`normalize_feed_url`], but are kept as they were written.
+  pub fn new(baseline_urls: Vec<String>, feeds: &[Feed]) -> Self {
+    let normalized_baseline = baseline_urls
+      .iter()
+      .map(|url| normalize_feed_url(url))
+      .collect::<HashSet<_>>();
+    let normalized_feeds = feeds
+      .iter()
+      .map(|feed| normalize_feed_url(&feed.url))
+      .collect::<HashSet<_>>();
+
+    Self {
+      added: feeds
+        .iter()
+        .filter(|feed| {
+          !normalized_baseline.contains(&normalize_feed_url(&feed.url))
+        })
+        .map(|feed| feed.url.clone())
+        .collect(),
+      removed: baseline_urls
+        .into_iter()
+        .filter(|url| !normalized_feeds.contains(&normalize_feed_url(url)))
+        .collect(),
+    }
+  }
+}
+
+/// Parses the feed URLs from a `--diff-json` baseline, which is either the
+/// JSON output of an earlier run or an OPML file.
+pub fn parse_baseline_urls(contents: &str) -> Result<Vec<String>> {
+  if let Ok(feeds) = serde_json::from_str::<Vec<Value>>(contents) {
+    return feeds
+      .iter()
+      .enumerate()
+      .map(|(index, feed)| {
+        feed
+          .as_str()
+          .or_else(|| feed["url"].as_str())
+          .map(ToString::to_string)
+          .ok_or_else(|| eyre!("Feed {index} doesn't have a URL"))
+      })
+      .collect();
+  }
+
+  let document = opml::OPML::from_str(contents)
+    .wrap_err("The baseline is neither a JSON array nor OPML")?;
+  let mut outlines = vec![];
+  collect_feed_outlines(&document.body.outlines, &mut outlines);
+  Ok(
+    outlines
+      .into_iter()
+      .filter_map(|outline| outline.xml_url.clone())
+      .collect(),
+  )
+}
+
+/// Collects the outlines that are feeds, including the ones in folders.
+pub(crate) fn collect_feed_outlines<'o>(
+  outlines: &'o [opml::Outline],
+  feed_outlines: &mut Vec<&'o opml::Outline>,
+) {
+  for outline in outlines {
+    if outline.xml_url.is_some() {
+      feed_outlines.push(outline);
+    }
+
+    collect_feed_outlines(&outline.outlines, feed_outlines);
+  }
+}
+
+/// Normalizes a feed URL for comparing, so the same feed written slightly
+/// differently in two runs isn't seen as a change.
+pub fn normalize_feed_url(url: &str) -> String {
+  let url = url.trim().trim_end_matches('/').to_lowercase();
+  match url.strip_prefix("http://") {
+    Some(rest) => format!("https://{rest}"),
+    None => url,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    fixtures::feed, steam::appid_to_rss_url, write_feeds, Format, LineEnding,
+    Reader,
+  };
+
+  #[test]
+  fn feed_urls_are_normalized() {
+    let normalized = "https://steamcommunity.com/games/440/rss";
+    for url in [
+      "https://steamcommunity.com/games/440/rss/",
+      "http://steamcommunity.com/games/440/rss",
+      " HTTPS://SteamCommunity.com/games/440/rss/ ",
+    ] {
+      assert_eq!(normalize_feed_url(url), normalized, "{url}");
+    }
+  }
+
+  #[test]
+  fn baseline_urls_are_read_from_json_and_opml() -> Result<()> {
+    let json = r#"["https://a/", {"url": "https://b/"}]"#;
+    assert_eq!(parse_baseline_urls(json)?, ["https://a/", "https://b/"]);
+    assert!(parse_baseline_urls(r#"[{"name": "No URL"}]"#).is_err());
+
+    let mut grouped = feed(570, "Dota 2");
+    grouped.group = Some("Friends".to_string());
+    let mut opml = vec![];
+    write_feeds(
+      Format::Opml,
+      Reader::Newsboat,
+      LineEnding::Lf,
+      &mut opml,
+      &[feed(440, "TF2"), grouped],
+    )?;
+    assert_eq!(
+      parse_baseline_urls(&String::from_utf8(opml)?)?,
+      [appid_to_rss_url(440), appid_to_rss_url(570)]
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn added_and_removed_feeds_are_found() {
+    let baseline = vec![
+      "http://steamcommunity.com/games/440/rss".to_string(),
+      "https://steamcommunity.com/games/730/rss/".to_string(),
+    ];
+    let diff = FeedDiff::new(baseline, &[feed(440, "TF2"), feed(570, "Dota")]);
+
+    assert_eq!(diff.added, [appid_to_rss_url(570)]);
+    assert_eq!(diff.removed, [appid_to_rss_url(730)]);
+  }
+}
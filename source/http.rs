@@ -0,0 +1,604 @@
+//! The HTTP client used for all requests, with a [`Transport`] that can be
+//! replaced so the rest of the library doesn't depend on the network.
+
+use std::{
+  fmt::{self, Display, Formatter},
+  fs::{create_dir_all, read_to_string, write, OpenOptions},
+  hash::{DefaultHasher, Hash, Hasher},
+  io::{Cursor, Read, Write},
+  path::PathBuf,
+  time::Duration,
+};
+
+use color_eyre::{eyre::bail, Report, Result};
+
+use crate::Args;
+
+/// The cookies Steam sets once its age check has been passed.
+pub const AGE_CHECK_COOKIES: &str = "birthtime=0; lastagecheckage=1-0-1970; \
+  mature_content=1; wants_mature_content=1";
+
+/// The User-Agent that is sent when `--user-agent-append` isn't used.
+pub const USER_AGENT: &str = "Steam Feeds (https://github.com/Bauke/steam-rss)";
+
+/// A request for a [`Transport`] to send.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Request {
+  /// The HTTP method, like `GET` or `HEAD`.
+  pub method: &'static str,
+
+  /// The URL to request.
+  pub url: String,
+
+  /// The headers to send, in the order they're set.
+  pub headers: Vec<(String, String)>,
+
+  /// How long the request can take before it's abandoned.
+  pub timeout: Option<Duration>,
+}
+
+impl Request {
+  /// Returns the value of a header, ignoring the case of its name. Like with
+  /// [`ureq`], the last header with a name replaces the earlier ones.
+  pub fn header(&self, name: &str) -> Option<&str> {
+    self
+      .headers
+      .iter()
+      .rev()
+      .find(|(header, _)| header.eq_ignore_ascii_case(name))
+      .map(|(_, value)| value.as_str())
+  }
+}
+
+/// A response returned by a [`Transport`].
+pub struct Response {
+  /// The final URL of the response, after following any redirects.
+  pub url: String,
+
+  /// The HTTP status code.
+  pub status: u16,
+
+  /// The media type from the `Content-Type` header, like `text/xml`.
+  pub content_type: String,
+
+  /// The body, which is only read when it's needed.
+  pub body: Box<dyn Read + Send>,
+}
+
+impl Response {
+  /// Creates a response with a body that is already in memory.
+  pub fn new<B: Into<Vec<u8>>>(
+    url: &str,
+    status: u16,
+    content_type: &str,
+    body: B,
+  ) -> Self {
+    Self {
+      url: url.to_string(),
+      status,
+      content_type: content_type.to_string(),
+      body: Box::new(Cursor::new(body.into())),
+    }
+  }
+}
+
+/// Sends requests for a [`Client`].
+///
+/// Responses with an error status are returned like any other, only not
+/// getting a response at all is an error. Closures taking a [`Request`] can
+/// be used as a transport too.
+pub trait Transport: Send + Sync {
+  /// Sends the request and returns its response.
+  fn send(&self, request: &Request) -> Result<Response>;
+}
+
+impl<F> Transport for F
+where
+  F: Fn(&Request) -> Result<Response> + Send + Sync,
+{
+  fn send(&self, request: &Request) -> Result<Response> {
+    self(request)
+  }
+}
+
+/// The [`Transport`] that sends requests over the network using [`ureq`].
+pub struct UreqTransport {
+  /// The agent that requests are made with.
+  pub agent: ureq::Agent,
+}
+
+impl UreqTransport {
+  /// Creates a transport that identifies itself with `user_agent`.
+  pub fn new(user_agent: &str) -> Self {
+    Self {
+      agent: ureq::AgentBuilder::new().user_agent(user_agent).build(),
+    }
+  }
+}
+
+impl Transport for UreqTransport {
+  fn send(&self, request: &Request) -> Result<Response> {
+    let mut ureq_request = request.headers.iter().fold(
+      self.agent.request(request.method, &request.url),
+      |ureq_request, (name, value)| ureq_request.set(name, value),
+    );
+    if let Some(timeout) = request.timeout {
+      ureq_request = ureq_request.timeout(timeout);
+    }
+
+    let response = match ureq_request.call() {
+      Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+      Err(ureq::Error::Transport(transport)) => return Err(transport.into()),
+    };
+
+    Ok(Response {
+      url: response.get_url().to_string(),
+      status: response.status(),
+      content_type: response.content_type().to_string(),
+      body: Box::new(response.into_reader()),
+    })
+  }
+}
+
+/// The error for a response with a 4xx or 5xx status code.
+#[derive(Debug)]
+pub struct StatusError {
+  /// The URL of the response, with any API key redacted.
+  pub url: String,
+
+  /// The HTTP status code.
+  pub status: u16,
+}
+
+impl Display for StatusError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: status code {}", self.url, self.status)
+  }
+}
+
+impl std::error::Error for StatusError {}
+
+/// The HTTP client used for all requests, which adds the headers and cookie
+/// from the CLI arguments to them.
+pub struct Client {
+  /// The Steam session cookie, only sent to steamcommunity.com.
+  pub cookie: Option<String>,
+
+  /// The time to sleep between requests, see [`Args::timeout`].
+  pub delay: Duration,
+
+  /// The directory to save every raw response body in, see
+  /// [`Args::dump_raw`].
+  pub dump_raw: Option<PathBuf>,
+
+  /// The extra headers to send with every request.
+  pub headers: Vec<(String, String)>,
+
+  /// The maximum amount of bytes to read from a response body.
+  pub max_body_size: Option<u64>,
+
+  /// The transport that sends the requests.
+  pub transport: Box<dyn Transport>,
+}
+
+impl Client {
+  /// Creates a client for the CLI arguments that sends its requests over
+  /// the network.
+  pub fn from_args(args: &Args) -> Result<Self> {
+    Self::new(args, UreqTransport::new(&user_agent(args)))
+  }
+
+  /// Creates a client for the CLI arguments that sends its requests with
+  /// `transport`. This reads the `--cookie-file` and creates the
+  /// `--dump-raw` directory.
+  pub fn new<T: Transport + 'static>(
+    args: &Args,
+    transport: T,
+  ) -> Result<Self> {
+    let cookie = match (&args.cookie, &args.cookie_file) {
+      (Some(cookie), _) => Some(cookie.clone()),
+      (None, Some(path)) => Some(read_to_string(path)?.trim().to_string()),
+      (None, None) => None,
+    };
+    if cookie
+      .as_deref()
+      .is_some_and(|cookie| cookie.contains(['\r', '\n']))
+    {
+      bail!("The Steam session cookie can't contain line breaks.");
+    }
+
+    if let Some(dump_raw) = &args.dump_raw {
+      create_dir_all(dump_raw)?;
+    }
+
+    Ok(Self {
+      cookie,
+      delay: Duration::from_millis(args.timeout),
+      dump_raw: args.dump_raw.clone(),
+      headers: args.http_header.clone(),
+      max_body_size: args.max_body_size,
+      transport: Box::new(transport),
+    })
+  }
+
+  /// Creates a GET request for `url` with all the extra headers set.
+  pub fn get(&self, url: &str) -> RequestBuilder<'_> {
+    self.request("GET", url, None)
+  }
+
+  /// Creates a GET request for `url` that also sends the cookies that skip
+  /// Steam's age check, for mature games whose feeds are behind one.
+  pub fn get_past_age_gate(&self, url: &str) -> RequestBuilder<'_> {
+    self.request("GET", url, Some(AGE_CHECK_COOKIES))
+  }
+
+  /// Creates a HEAD request for `url` with all the extra headers set.
+  pub fn head(&self, url: &str) -> RequestBuilder<'_> {
+    self.request("HEAD", url, None)
+  }
+
+  /// Creates a request for `url` with all the extra headers set, and
+  /// `cookies` added to the session cookie.
+  ///
+  /// Servers only read one `Cookie` header, so any from `--http-header` are
+  /// combined with the session cookie and `cookies` into a single one.
+  fn request(
+    &self,
+    method: &'static str,
+    url: &str,
+    cookies: Option<&str>,
+  ) -> RequestBuilder<'_> {
+    let (cookie_headers, mut headers): (Vec<_>, Vec<_>) = self
+      .headers
+      .iter()
+      .cloned()
+      .partition(|(name, _)| name.eq_ignore_ascii_case("Cookie"));
+    let session_cookie =
+      self.cookie.as_deref().filter(|_| is_community_url(url));
+    let cookie = cookie_headers
+      .iter()
+      .map(|(_, value)| value.as_str())
+      .chain(session_cookie)
+      .chain(cookies)
+      .filter(|cookie| !cookie.is_empty())
+      .collect::<Vec<_>>()
+      .join("; ");
+    if !cookie.is_empty() {
+      headers.push(("Cookie".to_string(), cookie));
+    }
+
+    RequestBuilder {
+      client: self,
+      request: Request {
+        method,
+        url: url.to_string(),
+        headers,
+        timeout: None,
+      },
+    }
+  }
+
+  /// Reads the body of a response as text. Invalid UTF-8 is replaced with
+  /// U+FFFD and a warning is logged, rather than aborting with an error.
+  pub fn read_body(&self, response: Response) -> Result<String> {
+    let url = redact_api_key(&response.url);
+    let mut bytes = vec![];
+    let limit = self.max_body_size.unwrap_or(u64::MAX);
+
+    // Read one byte past the limit to know whether the body is bigger.
+    response
+      .body
+      .take(limit.saturating_add(1))
+      .read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > limit {
+      bail!(
+        "Response from {url} is larger than {limit} bytes (--max-body-size)."
+      );
+    }
+
+    if let Some(dump_raw) = &self.dump_raw {
+      let mut hasher = DefaultHasher::new();
+      url.hash(&mut hasher);
+      let file_name = format!("{:016x}.txt", hasher.finish());
+      write(dump_raw.join(&file_name), &bytes)?;
+
+      // Keep track of which file belongs to which URL.
+      let mut index = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(dump_raw.join("urls.txt"))?;
+      writeln!(index, "{file_name} {url}")?;
+    }
+
+    Ok(match String::from_utf8(bytes) {
+      Ok(body) => body,
+      Err(error) => {
+        eprintln!(
+          "Response from {url} isn't valid UTF-8, decoding it lossily."
+        );
+        String::from_utf8_lossy(error.as_bytes()).into_owned()
+      }
+    })
+  }
+}
+
+/// A request created by a [`Client`] that hasn't been sent yet.
+pub struct RequestBuilder<'c> {
+  /// The client that sends the request.
+  client: &'c Client,
+
+  /// The request to send.
+  pub request: Request,
+}
+
+impl RequestBuilder<'_> {
+  /// Abandons the request when it takes longer than `timeout`.
+  pub fn timeout(mut self, timeout: Duration) -> Self {
+    self.request.timeout = Some(timeout);
+    self
+  }
+
+  /// Sends the request. Responses with a 4xx or 5xx status code are
+  /// returned as a [`StatusError`].
+  pub fn call(self) -> Result<Response> {
+    let response = self.client.transport.send(&self.request)?;
+    if response.status >= 400 {
+      return Err(Report::new(StatusError {
+        url: redact_api_key(&response.url),
+        status: response.status,
+      }));
+    }
+
+    Ok(response)
+  }
+}
+
+/// Returns the User-Agent to send, which is [`USER_AGENT`] with the
+/// `--user-agent-append` text after it.
+pub fn user_agent(args: &Args) -> String {
+  match &args.user_agent_append {
+    Some(append) => format!("{USER_AGENT} {append}"),
+    None => USER_AGENT.to_string(),
+  }
+}
+
+/// Replaces the value of a `key` query parameter in a URL, so Steam Web API
+/// keys don't end up in `--dump-raw` files or error messages.
+pub fn redact_api_key(url: &str) -> String {
+  let Some(start) = url
+    .find("?key=")
+    .or_else(|| url.find("&key="))
+    .map(|index| index + "?key=".len())
+  else {
+    return url.to_string();
+  };
+
+  let end = url[start..].find('&').map_or(url.len(), |end| start + end);
+  format!("{}REDACTED{}", &url[..start], &url[end..])
+}
+
+/// Checks whether an error was caused by a request timing out.
+pub fn is_timeout(error: &Report) -> bool {
+  error.chain().any(|cause| {
+    cause.downcast_ref::<std::io::Error>().is_some_and(|error| {
+      matches!(
+        error.kind(),
+        std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+      )
+    })
+  })
+}
+
+/// Checks whether a URL points to steamcommunity.com.
+pub fn is_community_url(url: &str) -> bool {
+  let host = url
+    .split_once("://")
+    .map_or(url, |(_, rest)| rest)
+    .split(['/', '?', '#'])
+    .next()
+    .unwrap_or_default()
+    .to_ascii_lowercase();
+  host == "steamcommunity.com" || host.ends_with(".steamcommunity.com")
+}
+
+#[cfg(test)]
+mod tests {
+  use color_eyre::eyre::eyre;
+
+  use super::*;
+  use crate::fixtures::{args, client};
+
+  /// Creates a client with the CLI arguments that never sends anything.
+  fn offline_client(arguments: &[&str]) -> Client {
+    client(&args(arguments), |request| {
+      bail!("Unexpected request: {}", request.url)
+    })
+    .0
+  }
+
+  #[test]
+  fn invalid_utf8_is_decoded_lossily() -> Result<()> {
+    let client = offline_client(&[]);
+    let response = Response::new(
+      "https://steamcommunity.com/games/440/rss/",
+      200,
+      "text/xml",
+      b"<title>Caf\xe9 \xff</title>".to_vec(),
+    );
+
+    assert_eq!(
+      client.read_body(response)?,
+      "<title>Caf\u{fffd} \u{fffd}</title>"
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn timeouts_are_recognized() {
+    use std::io::{Error, ErrorKind};
+
+    let timed_out = Report::from(Error::new(ErrorKind::TimedOut, "slow"));
+    assert!(is_timeout(&timed_out.wrap_err("Couldn't scan")));
+    assert!(is_timeout(&Report::from(Error::from(
+      ErrorKind::WouldBlock
+    ))));
+    assert!(!is_timeout(&Report::from(Error::from(ErrorKind::NotFound))));
+    assert!(!is_timeout(&eyre!("HTTP 404")));
+  }
+
+  #[test]
+  fn http_headers_are_sent() -> Result<()> {
+    let (client, requests) = client(
+      &args(&[
+        "--http-header",
+        "Accept-Language: nl",
+        "--http-header",
+        "X-Empty:",
+      ]),
+      |request| Ok(Response::new(&request.url, 200, "text/xml", "")),
+    );
+    client
+      .get("https://steamcommunity.com/games/440/rss/")
+      .call()?;
+    client
+      .head("https://store.steampowered.com/feeds/news/app/440/")
+      .call()?;
+
+    for request in requests.lock().unwrap().iter() {
+      assert_eq!(request.header("accept-language"), Some("nl"));
+      assert_eq!(request.header("X-Empty"), Some(""));
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn session_cookie_is_only_sent_to_steamcommunity() -> Result<()> {
+    let (client, requests) =
+      client(&args(&["--cookie", "steamLoginSecure=secret"]), |request| {
+        Ok(Response::new(&request.url, 200, "text/html", ""))
+      });
+    client
+      .get("https://steamcommunity.com/id/Bauke/games/?tab=all")
+      .call()?;
+    client
+      .get("https://store.steampowered.com/app/440")
+      .call()?;
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(
+      requests[0].header("Cookie"),
+      Some("steamLoginSecure=secret")
+    );
+    assert_eq!(requests[1].header("Cookie"), None);
+    Ok(())
+  }
+
+  #[test]
+  fn session_cookies_are_read_from_a_file() -> Result<()> {
+    let path = std::env::temp_dir()
+      .join(format!("steam-rss-cookie-{}.txt", std::process::id()));
+    write(&path, "steamLoginSecure=secret\n")?;
+    let client = offline_client(&["--cookie-file", path.to_str().unwrap()]);
+    assert_eq!(client.cookie.as_deref(), Some("steamLoginSecure=secret"));
+
+    write(&path, "steamLoginSecure=secret\nInjected: header")?;
+    let lines = args(&["--cookie-file", path.to_str().unwrap()]);
+    assert!(Client::new(&lines, |_: &Request| unreachable!()).is_err());
+
+    std::fs::remove_file(path)?;
+    Ok(())
+  }
+
+  #[test]
+  fn raw_bodies_are_dumped() -> Result<()> {
+    let dump_raw = std::env::temp_dir()
+      .join(format!("steam-rss-dump-raw-{}", std::process::id()));
+    let client = offline_client(&["--dump-raw", dump_raw.to_str().unwrap()]);
+    let url = "https://api.steampowered.com/A/v1/?key=SECRET&steamid=1";
+    client.read_body(Response::new(url, 200, "application/json", "{}"))?;
+
+    let index = read_to_string(dump_raw.join("urls.txt"))?;
+    let (file_name, dumped_url) = index.trim_end().split_once(' ').unwrap();
+    assert_eq!(dumped_url, redact_api_key(url));
+    assert_eq!(read_to_string(dump_raw.join(file_name))?, "{}");
+    assert!(!index.contains("SECRET"));
+
+    std::fs::remove_dir_all(dump_raw)?;
+    Ok(())
+  }
+
+  #[test]
+  fn bodies_over_the_size_limit_fail() {
+    let client = offline_client(&["--max-body-size", "4"]);
+    let url = "https://steamcommunity.com/id/Bauke/games/?tab=all";
+    let body = |body| Response::new(url, 200, "text/html", body);
+
+    assert_eq!(client.read_body(body("four")).unwrap(), "four");
+    let error = client.read_body(body("fives")).unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      format!("Response from {url} is larger than 4 bytes (--max-body-size).")
+    );
+  }
+
+  #[test]
+  fn user_agent_appends_are_added() {
+    assert_eq!(user_agent(&args(&[])), USER_AGENT);
+    assert_eq!(
+      user_agent(&args(&["--user-agent-append", " (contact: me@example.com) "])),
+      "Steam Feeds (https://github.com/Bauke/steam-rss) (contact: me@example.com)"
+    );
+  }
+
+  #[test]
+  fn cookies_are_combined_into_one_header() -> Result<()> {
+    let (client, requests) = client(
+      &args(&[
+        "--http-header",
+        "Cookie: Steam_Language=dutch",
+        "--cookie",
+        "steamLoginSecure=secret",
+      ]),
+      |request| Ok(Response::new(&request.url, 200, "text/xml", "")),
+    );
+    client
+      .get_past_age_gate("https://steamcommunity.com/games/440/rss/")
+      .call()?;
+    client
+      .get_past_age_gate("https://store.steampowered.com/feeds/news/app/440/")
+      .call()?;
+
+    let requests = requests.lock().unwrap();
+    for (request, expected) in requests.iter().zip([
+      format!(
+        "Steam_Language=dutch; steamLoginSecure=secret; {AGE_CHECK_COOKIES}"
+      ),
+      format!("Steam_Language=dutch; {AGE_CHECK_COOKIES}"),
+    ]) {
+      let cookies = request
+        .headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("Cookie"))
+        .map(|(_, value)| value.as_str())
+        .collect::<Vec<_>>();
+      assert_eq!(cookies, [expected]);
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn api_keys_are_redacted() {
+    assert_eq!(
+      redact_api_key("https://api.steampowered.com/A/v1/?key=SECRET&steamid=1"),
+      "https://api.steampowered.com/A/v1/?key=REDACTED&steamid=1"
+    );
+    assert_eq!(
+      redact_api_key("https://api.steampowered.com/A/v1/?steamid=1&key=SECRET"),
+      "https://api.steampowered.com/A/v1/?steamid=1&key=REDACTED"
+    );
+    assert_eq!(
+      redact_api_key("https://steamcommunity.com/games/440/rss/"),
+      "https://steamcommunity.com/games/440/rss/"
+    );
+  }
+}
@@ -0,0 +1,239 @@
+//! # Steam RSS
+//!
+//! > **Get RSS feeds for Steam games.**
+//!
+//! The library contains everything the `steam-rss` binary does to find
+//! feeds: [`resolve`] scans and verifies the inputs from [`Args`] using a
+//! [`Client`], and writes the feeds to a [`FeedSink`]. Other programs can
+//! implement their own sink to handle the feeds in their own way, or their
+//! own [`http::Transport`] to make the requests.
+
+pub mod args;
+pub mod date;
+pub mod diff;
+pub mod http;
+pub mod resolve;
+pub mod sink;
+pub mod steam;
+
+use {clap::ValueEnum, serde::Serialize};
+
+pub use crate::{
+  args::Args,
+  http::Client,
+  resolve::{resolve, SkipReason, SkippedInput, Summary},
+  sink::{sink_for_format, write_feeds, FeedSink},
+};
+
+/// The formats that feeds can be output as.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Format {
+  /// One feed URL per line.
+  Urls,
+
+  /// An OPML document with an outline for every feed.
+  Opml,
+
+  /// A single JSON array containing every feed.
+  Json,
+
+  /// One JSON object per feed, separated by newlines.
+  Ndjson,
+
+  /// A CSV table with a header row and one row per feed.
+  Csv,
+
+  /// A shell script that adds every feed to the `--reader`.
+  Script,
+
+  /// An HTML page with a list of links to every feed.
+  Html,
+}
+
+/// The feed readers that `--format script` can generate a script for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Reader {
+  /// Canto, using `canto-remote addfeed`.
+  Canto,
+
+  /// Newsboat, by appending to `~/.newsboat/urls`.
+  Newsboat,
+
+  /// rss2email, using `r2e add`.
+  Rss2email,
+}
+
+/// The line endings that can be used for output.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum LineEnding {
+  /// Unix-style `\n` line endings.
+  Lf,
+
+  /// Windows-style `\r\n` line endings.
+  Crlf,
+}
+
+/// A simple feed struct.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Feed {
+  /// How long ago the feed was last updated, like `3 days ago`. Only
+  /// available for verified feeds.
+  pub age: Option<String>,
+
+  /// The AppID of the game the feed is for.
+  pub appid: usize,
+
+  /// A potential alternate friendly URL, some games use their friendly URL
+  /// name instead of their AppID for their feed.
+  pub friendly_url: Option<String>,
+
+  /// The OPML folder to put the feed in, see `--group-name-template`.
+  pub group: Option<String>,
+
+  /// The URL of the game's icon, only used for the HTML output.
+  pub icon: Option<String>,
+
+  /// The `lastBuildDate` of the feed, only available for verified feeds.
+  pub last_build_date: Option<String>,
+
+  /// The name of the game, when it was found by scanning a user's games.
+  pub name: Option<String>,
+
+  /// The display name of the user whose games this feed was found in.
+  pub source_user: Option<String>,
+
+  /// The text to use for the feed in the OPML output, see `--opml-text-from`
+  /// for where it comes from.
+  pub text: Option<String>,
+
+  /// The title of the feed, only available for verified feeds.
+  pub title: Option<String>,
+
+  /// The URL of the feed.
+  pub url: String,
+
+  /// The result of verifying the feed, or `None` if it wasn't verified.
+  pub verification: Option<VerificationStatus>,
+}
+
+impl Feed {
+  /// Creates a feed for a game with only its AppID and URL.
+  pub fn new(appid: usize, url: String) -> Self {
+    Self {
+      appid,
+      url,
+      ..Default::default()
+    }
+  }
+}
+
+/// The result of successfully verifying a feed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VerificationStatus {
+  /// The feed's URL returned XML.
+  Valid,
+
+  /// The feed's URL didn't return XML but its friendly URL did, which is used
+  /// instead.
+  FriendlyUsed,
+
+  /// The feed's URL didn't return XML but the URL from the other `--prefer`
+  /// source did, which is used instead.
+  AlternateUsed,
+}
+
+/// Helpers shared by the tests of every module.
+#[cfg(test)]
+pub(crate) mod fixtures {
+  use std::sync::{Arc, Mutex};
+
+  use {clap::Parser, color_eyre::Result};
+
+  use crate::{
+    http::{Request, Response},
+    Args, Client, Feed, Summary,
+  };
+
+  /// Creates a feed for an AppID with its community feed URL and a text.
+  pub fn feed(appid: usize, text: &str) -> Feed {
+    Feed {
+      text: Some(text.to_string()),
+      ..Feed::new(appid, crate::steam::appid_to_rss_url(appid))
+    }
+  }
+
+  /// Parses the CLI arguments that follow `steam-rss`, without sleeping
+  /// between requests.
+  pub fn args(arguments: &[&str]) -> Args {
+    Args::parse_from(["steam-rss", "--timeout", "0"].iter().chain(arguments))
+  }
+
+  /// The requests a [`client`] has sent, in the order they were sent.
+  pub type Requests = Arc<Mutex<Vec<Request>>>;
+
+  /// Creates a client for `args` whose requests are answered by `respond`
+  /// and recorded.
+  pub fn client<F>(args: &Args, respond: F) -> (Client, Requests)
+  where
+    F: Fn(&Request) -> Result<Response> + Send + Sync + 'static,
+  {
+    let requests = Requests::default();
+    let recorded = requests.clone();
+    let client = Client::new(args, move |request: &Request| {
+      recorded.lock().unwrap().push(request.clone());
+      respond(request)
+    })
+    .unwrap();
+    (client, requests)
+  }
+
+  /// Resolves the feeds for the CLI arguments with `respond` answering the
+  /// requests. Returns the result, the feeds that were written and the
+  /// requests that were sent.
+  pub fn resolve<F>(
+    arguments: &[&str],
+    respond: F,
+  ) -> (Result<Summary>, Vec<Feed>, Vec<Request>)
+  where
+    F: Fn(&Request) -> Result<Response> + Send + Sync + 'static,
+  {
+    let args = args(arguments);
+    let (client, requests) = client(&args, respond);
+    let mut feeds = vec![];
+    let summary = crate::resolve(&args, &client, &mut feeds);
+    let requests = requests.lock().unwrap().clone();
+    (summary, feeds, requests)
+  }
+
+  /// Responds with a feed titled `title`, with items published at `dates`.
+  pub fn rss(request: &Request, title: &str, dates: &[&str]) -> Response {
+    let items = dates
+      .iter()
+      .map(|date| format!("<item><pubDate>{date}</pubDate></item>"))
+      .collect::<String>();
+    let body =
+      format!("<rss><channel><title>{title}</title>{items}</channel></rss>");
+    Response::new(&request.url, 200, "text/xml", body)
+  }
+
+  /// Responds with an HTML page, like Steam does for feeds that don't exist.
+  pub fn html(request: &Request, body: &str) -> Response {
+    Response::new(&request.url, 200, "text/html", body)
+  }
+
+  /// Responds with the games page of a user named `name` who owns the games
+  /// in `games_json`.
+  pub fn games_page(
+    request: &Request,
+    name: &str,
+    games_json: &str,
+  ) -> Response {
+    let body = format!(
+      "<title>Steam Community :: {name} :: Games</title>\n\
+       var rgGames = {games_json};\n\
+       var rgChangingGames = [];"
+    );
+    html(request, &body)
+  }
+}
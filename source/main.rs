@@ -2,238 +2,530 @@
 //!
 //! > **Get RSS feeds for Steam games.**
 
-use std::{thread::sleep, time::Duration};
+use std::{
+  cell::Cell,
+  collections::BTreeMap,
+  fs::{read_to_string, write, File, OpenOptions},
+  io::{stdin, stdout, Read, Write},
+  num::NonZeroUsize,
+};
 
 use {
-  clap::Parser,
-  color_eyre::{install, Result},
-  regex::Regex,
-  serde::Deserialize,
+  clap::{Parser, ValueEnum},
+  color_eyre::{
+    eyre::{bail, WrapErr},
+    install, Result,
+  },
+  serde::{Deserialize, Serialize},
   serde_json::Value,
 };
 
-/// CLI arguments struct using [`clap`]'s Derive API.
-#[derive(Debug, Parser)]
-#[clap(about, author, version)]
-pub struct Args {
-  /// A game's AppID, can be used multiple times.
-  #[clap(short, long)]
-  pub appid: Vec<usize>,
-
-  /// Output the feeds as OPML.
-  #[clap(long)]
-  pub opml: bool,
-
-  /// The time in milliseconds to sleep between HTTP requests.
-  #[clap(short, long, default_value = "250")]
-  pub timeout: u64,
-
-  /// Verify potential feeds by downloading them and checking if they return XML.
-  #[clap(short, long)]
-  pub verify: bool,
-
-  /// A game's store URL, can be used multiple times.
-  #[clap(long)]
-  pub url: Vec<String>,
-
-  /// A person's steamcommunity.com ID or full URL, can be used multiple times.
-  #[clap(long)]
-  pub user: Vec<String>,
+use steam_rss::{
+  date,
+  diff::{parse_baseline_urls, FeedDiff},
+  resolve,
+  resolve::fetch_icons,
+  sink::{validate_opml, LineEndingWriter},
+  write_feeds, Args, Client, Feed, FeedSink, Format, Summary,
+};
+
+/// An input read from stdin with `--stdin-json`.
+#[derive(Debug, Deserialize, Eq, PartialEq)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum StdinTask {
+  /// A game's AppID, like `--appid`.
+  Appid(usize),
+
+  /// A game's store URL, like `--url`.
+  Url(String),
+
+  /// A person's User ID or URL, like `--user`.
+  User(String),
+}
+
+/// The data a `--template-file` is rendered with.
+#[derive(Debug, Serialize)]
+pub struct TemplateContext<'a> {
+  /// The amount of feeds.
+  pub count: usize,
+
+  /// All the feeds to output.
+  pub feeds: &'a [Feed],
+
+  /// When the feeds were generated, in seconds since the Unix epoch.
+  pub generated_at: i64,
+
+  /// The amount of inputs that didn't result in a feed.
+  pub skipped: usize,
+
+  /// The version of steam-rss that generated the feeds.
+  pub version: &'static str,
+}
+
+/// Collects the feeds from [`resolve`] so they can be processed further
+/// before they're output. While verifying, the feeds collected so far are
+/// written to the `--output` file every `--checkpoint-every` feeds.
+struct CollectSink<F: FnMut(&[Feed]) -> Result<()>> {
+  /// The feeds collected so far.
+  feeds: Vec<Feed>,
+
+  /// After how many feeds a checkpoint is written.
+  checkpoint_every: Option<usize>,
+
+  /// Writes a checkpoint with the feeds collected so far.
+  write_checkpoint: F,
+}
+
+impl<F: FnMut(&[Feed]) -> Result<()>> FeedSink for CollectSink<F> {
+  fn write_feed(&mut self, feed: &Feed) -> Result<()> {
+    self.feeds.push(feed.clone());
+    if self
+      .checkpoint_every
+      .is_some_and(|n| self.feeds.len().is_multiple_of(n))
+    {
+      (self.write_checkpoint)(&self.feeds)?;
+    }
+
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<()> {
+    Ok(())
+  }
+}
+
+/// Parses the JSON array of tasks read from stdin with `--stdin-json`. All
+/// the malformed tasks are reported at once, with their index.
+fn parse_stdin_tasks(tasks: &str) -> Result<Vec<StdinTask>> {
+  let tasks = serde_json::from_str::<Vec<Value>>(tasks)
+    .wrap_err("Couldn't parse stdin as a JSON array (--stdin-json)")?;
+
+  let mut parsed_tasks = vec![];
+  let mut malformed_tasks = vec![];
+  for (index, task) in tasks.into_iter().enumerate() {
+    match serde_json::from_value::<StdinTask>(task) {
+      Ok(task) => parsed_tasks.push(task),
+      Err(error) => malformed_tasks.push(format!("{index}: {error}")),
+    }
+  }
+
+  if !malformed_tasks.is_empty() {
+    bail!(
+      "Malformed tasks on stdin (--stdin-json):\n{}",
+      malformed_tasks.join("\n")
+    );
+  }
+
+  Ok(parsed_tasks)
 }
 
-/// A simple feed struct.
-#[derive(Debug)]
-pub struct Feed {
-  /// A potential alternate friendly URL, see [`SteamApp::friendly_url`] for an
-  /// explanation.
-  pub friendly_url: Option<String>,
+/// Writes every `--format` with its description, for `--list-formats`.
+fn list_formats<W: Write>(mut writer: W) -> Result<()> {
+  for format in Format::value_variants() {
+    let value = format.to_possible_value().unwrap();
+    let help = value
+      .get_help()
+      .map(ToString::to_string)
+      .unwrap_or_default();
+    writeln!(writer, "{:<8}{help}", value.get_name())?;
+  }
 
-  /// The text to use for the feed in the OPML output.
-  pub text: Option<String>,
+  Ok(())
+}
 
-  /// The URL of the feed.
-  pub url: String,
+/// Fails when less than `min_success_rate` percent of the verified feeds were
+/// valid, for `--min-success-rate`. Not verifying any feeds counts as 100%.
+fn check_success_rate(summary: &Summary, min_success_rate: u8) -> Result<()> {
+  let (verified_count, valid_count) = (summary.verified, summary.valid);
+  let success_rate = if verified_count == 0 {
+    100.0
+  } else {
+    valid_count as f64 / verified_count as f64 * 100.0
+  };
+  if success_rate < f64::from(min_success_rate) {
+    bail!(
+      "Only {success_rate:.1}% of {verified_count} verified feeds were valid \
+       (--min-success-rate {min_success_rate})."
+    );
+  }
+
+  Ok(())
 }
 
-/// A small representation of a Steam game that is parsed from JSON.
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct SteamApp {
-  /// The AppID of the game.
-  pub appid: usize,
-
-  /// The name of the game.
-  pub name: String,
-
-  /// A friendly URL name of the game, some feeds will use this instead of their
-  /// AppID for their RSS feed.
-  ///
-  /// For example, [Portal's feed](https://steamcommunity.com/games/Portal/rss)
-  /// uses `Portal`, instead of
-  /// [its AppID 400](https://steamcommunity.com/games/400/rss).
-  ///
-  /// Some games may also have a friendly URL different from their AppID but
-  /// don't use it for their feed. Steam is weird.
-  #[serde(rename = "friendlyURL")]
-  pub friendly_url: Value,
+/// Renders a `--template-file` with the feeds in `context`.
+fn render_template(
+  template: &str,
+  context: &TemplateContext,
+) -> Result<String> {
+  let mut renderer = tinytemplate::TinyTemplate::new();
+  renderer.add_template("template", template)?;
+  Ok(renderer.render("template", context)?)
+}
+
+/// Records the URLs of the verified feeds in a `--history-file`, and returns
+/// the feeds whose URL changed since the previous run with their old URL.
+fn update_history<'f>(
+  history: &mut BTreeMap<usize, String>,
+  feeds: &'f [Feed],
+) -> Vec<(&'f Feed, String)> {
+  feeds
+    .iter()
+    .filter(|feed| feed.verification.is_some())
+    .filter_map(|feed| {
+      history
+        .insert(feed.appid, feed.url.clone())
+        .filter(|previous_url| *previous_url != feed.url)
+        .map(|previous_url| (feed, previous_url))
+    })
+    .collect()
 }
 
 fn main() -> Result<()> {
   install()?;
 
-  let args = Args::parse();
-  let timeout = Duration::from_millis(args.timeout);
-
-  let ureq_agent = ureq::AgentBuilder::new()
-    .user_agent("Steam Feeds (https://github.com/Bauke/steam-rss)")
-    .build();
-  let mut potential_feeds = vec![];
-  let mut feeds_to_output = vec![];
-
-  let store_url_regex =
-    Regex::new(r"(?i)^https?://store.steampowered.com/app/(?P<appid>\d+)")?;
-  let user_json_regex = Regex::new(r"var rgGames = (?P<json>\[.+\]);\s+var")?;
-  let user_id_regex = Regex::new(r"(i?)^\w+$")?;
-  let user_url_regex =
-    Regex::new(r"(?i)https?://steamcommunity.com/id/(?P<userid>\w+)")?;
-
-  for appid in args.appid {
-    potential_feeds.push(Feed {
-      friendly_url: None,
-      text: Some(format!("Steam AppID {appid}")),
-      url: appid_to_rss_url(appid),
-    });
-  }
-
-  for url in args.url {
-    let appid = store_url_regex
-      .captures(&url)
-      .and_then(|captures| captures.name("appid"))
-      .and_then(|appid_match| appid_match.as_str().parse::<usize>().ok());
-    if let Some(appid) = appid {
-      potential_feeds.push(Feed {
-        friendly_url: None,
-        text: Some(format!("Steam AppID {appid}")),
-        url: appid_to_rss_url(appid),
-      });
+  let mut args = Args::parse();
+  if args.list_formats {
+    return list_formats(stdout().lock());
+  }
+
+  if args.stdin_json {
+    let mut tasks = String::new();
+    stdin().read_to_string(&mut tasks)?;
+    for task in parse_stdin_tasks(&tasks)? {
+      match task {
+        StdinTask::Appid(appid) => args.appid.push(appid),
+        StdinTask::Url(url) => args.url.push(url),
+        StdinTask::User(user) => args.user.push(user),
+      }
     }
   }
 
-  for user in args.user {
-    let user_url = if user_id_regex.is_match(&user) {
-      userid_to_games_url(user)
-    } else if let Some(user) = user_url_regex
-      .captures(&user)
-      .and_then(|captures| captures.name("userid"))
-    {
-      userid_to_games_url(user.as_str())
+  let format = if args.opml { Format::Opml } else { args.format };
+  if args.interactive_resolve && cfg!(not(feature = "dialoguer")) {
+    eprintln!(
+      "--interactive-resolve needs the \"dialoguer\" feature, ambiguous users \
+       will be treated as SteamID64s."
+    );
+  }
+  if let Some(output) = args.output.as_deref().filter(|_| args.no_clobber) {
+    if output.exists() {
+      bail!("{} already exists (--no-clobber).", output.display());
+    }
+  }
+  let client = Client::from_args(&args)?;
+
+  // Opens the `--output` file or stdout when there is none. The file is only
+  // created without overwriting for the first write with `--no-clobber`,
+  // after that it's our own checkpoint that gets replaced.
+  let wrote_output = Cell::new(false);
+  let open_output = || -> Result<Box<dyn Write>> {
+    let create_new = args.no_clobber && !wrote_output.replace(true);
+    Ok(match &args.output {
+      Some(path) if create_new => {
+        Box::new(OpenOptions::new().write(true).create_new(true).open(path)?)
+      }
+      Some(path) => Box::new(File::create(path)?),
+      None => Box::new(stdout().lock()),
+    })
+  };
+
+  let mut sink = CollectSink {
+    feeds: vec![],
+    checkpoint_every: args
+      .checkpoint_every
+      .filter(|_| args.verifies())
+      .map(NonZeroUsize::get),
+    write_checkpoint: |feeds: &[Feed]| {
+      write_feeds(format, args.reader, args.line_ending, open_output()?, feeds)
+    },
+  };
+  let summary = resolve(&args, &client, &mut sink)?;
+  let mut feeds_to_output = sink.feeds;
+
+  if let Some(history_file) = &args.history_file {
+    let mut history = if history_file.exists() {
+      serde_json::from_str::<BTreeMap<usize, String>>(&read_to_string(
+        history_file,
+      )?)
+      .wrap_err_with(|| format!("Couldn't parse {}", history_file.display()))?
     } else {
-      continue;
+      BTreeMap::new()
     };
 
-    let body = ureq_agent.get(&user_url).call()?.into_string()?;
-    sleep(timeout);
-
-    let games_json = user_json_regex
-      .captures(&body)
-      .and_then(|captures| captures.name("json"))
-      .map(|json| json.as_str());
-    if let Some(games_json) = games_json {
-      let games = serde_json::from_str::<Vec<SteamApp>>(games_json)?;
-      for game in games {
-        let friendly_url = if game.friendly_url.is_string() {
-          Some(appid_to_rss_url(game.friendly_url.as_str().unwrap()))
-        } else {
-          None
-        };
-
-        potential_feeds.push(Feed {
-          friendly_url,
-          text: Some(game.name),
-          url: appid_to_rss_url(game.appid),
-        });
-      }
-    } else {
-      eprintln!("Couldn't scan games from: {user_url}");
+    for (feed, previous_url) in update_history(&mut history, &feeds_to_output) {
       eprintln!(
-        "Make sure \"Game Details\" in Privacy Settings is set to Public."
+        "The feed for AppID {} moved from {previous_url} to {}, \
+         subscriptions to the old URL may be stale.",
+        feed.appid, feed.url
       );
-      continue;
     }
+
+    write(history_file, serde_json::to_string_pretty(&history)?)?;
+  }
+
+  if args.fetch_icons && format == Format::Html {
+    fetch_icons(&client, &mut feeds_to_output);
   }
 
-  if args.verify {
-    let verify_feed = |url: &str| -> Result<_> {
-      let response = ureq_agent.get(url).call()?;
-      sleep(timeout);
-      Ok((
-        response.content_type() == "text/xml",
-        response.into_string()?,
-      ))
+  if let Some(baseline) = &args.diff_json {
+    let baseline_urls = parse_baseline_urls(&read_to_string(baseline)?)
+      .wrap_err_with(|| format!("Couldn't parse {}", baseline.display()))?;
+    let diff = FeedDiff::new(baseline_urls, &feeds_to_output);
+
+    let output = open_output()?;
+    writeln!(
+      LineEndingWriter::new(args.line_ending, output),
+      "{}",
+      serde_json::to_string_pretty(&diff)?
+    )?;
+  } else if feeds_to_output.is_empty() {
+    eprintln!("No feeds found.");
+  } else if let Some(template_file) = &args.template_file {
+    let context = TemplateContext {
+      count: feeds_to_output.len(),
+      feeds: &feeds_to_output,
+      generated_at: date::now(),
+      skipped: summary.skipped.len(),
+      version: env!("CARGO_PKG_VERSION"),
     };
+    let template = read_to_string(template_file)?;
+    let rendered =
+      render_template(&template, &context).wrap_err_with(|| {
+        format!("Couldn't render {}", template_file.display())
+      })?;
+    let output = open_output()?;
+    LineEndingWriter::new(args.line_ending, output)
+      .write_all(rendered.as_bytes())?;
+  } else if args.validate_opml && format == Format::Opml {
+    let mut opml = vec![];
+    write_feeds(
+      format,
+      args.reader,
+      args.line_ending,
+      &mut opml,
+      &feeds_to_output,
+    )?;
+    validate_opml(&String::from_utf8(opml.clone())?, &feeds_to_output)?;
+    open_output()?.write_all(&opml)?;
+  } else {
+    let output = open_output()?;
+    write_feeds(
+      format,
+      args.reader,
+      args.line_ending,
+      output,
+      &feeds_to_output,
+    )?;
+  }
 
-    for mut potential_feed in potential_feeds {
-      let (mut is_valid_feed, mut body) = verify_feed(&potential_feed.url)?;
-
-      // If the potential URL doesn't return `text/xml`, try the friendly URL
-      // if one exists.
-      if !is_valid_feed && potential_feed.friendly_url.is_some() {
-        let friendly_url = potential_feed.friendly_url.as_deref().unwrap();
-        (is_valid_feed, body) = verify_feed(friendly_url)?;
-        if is_valid_feed {
-          potential_feed.url = friendly_url.to_string();
-        }
-      }
+  if let Some(skipped_file) = &args.skipped_file {
+    write(
+      skipped_file,
+      serde_json::to_string_pretty(&summary.skipped)?,
+    )?;
+  }
 
-      let verified_feed = if is_valid_feed {
-        let title_start = body.find("<title>").unwrap() + 7;
-        let title_end = body.find("</title>").unwrap();
-        Feed {
-          text: Some(body[title_start..title_end].to_string()),
-          ..potential_feed
-        }
-      } else {
-        continue;
-      };
-
-      feeds_to_output.push(verified_feed);
+  if args.count_by_user {
+    for count in &summary.user_counts {
+      eprintln!(
+        "{}: {} feeds, {} not found in earlier inputs",
+        count.user, count.feeds, count.new_feeds
+      );
     }
-  } else {
-    feeds_to_output.append(&mut potential_feeds);
   }
 
-  let mut opml_document = opml::OPML {
-    head: None,
-    ..Default::default()
-  };
+  let max_failures = args.max_failures.map_or(usize::MAX, NonZeroUsize::get);
+  if summary.failures >= max_failures {
+    bail!(
+      "Stopped early after {} failures (--max-failures).",
+      summary.failures
+    );
+  }
 
-  if feeds_to_output.is_empty() {
-    eprintln!("No feeds found.");
-    return Ok(());
+  if let Some(min_success_rate) = args.min_success_rate {
+    check_success_rate(&summary, min_success_rate)?;
   }
 
-  for feed in feeds_to_output {
-    if args.opml {
-      opml_document
-        .add_feed(&feed.text.unwrap_or_else(|| feed.url.clone()), &feed.url);
-    } else {
-      println!("{}", feed.url);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use steam_rss::VerificationStatus;
+
+  use super::*;
+
+  #[test]
+  fn every_format_is_listed() -> Result<()> {
+    let mut listing = vec![];
+    list_formats(&mut listing)?;
+    let listing = String::from_utf8(listing)?;
+
+    let lines = listing.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), Format::value_variants().len());
+    for (line, format) in lines.iter().zip(Format::value_variants()) {
+      let value = format.to_possible_value().unwrap();
+      assert!(line.starts_with(value.get_name()), "{line}");
+      assert!(line.len() > 8, "{format:?} has no description");
     }
+    Ok(())
   }
 
-  if args.opml {
-    println!("{}", opml_document.to_string()?);
+  #[test]
+  fn checkpoints_are_written_at_the_interval() -> Result<()> {
+    let mut checkpoints = Vec::<Vec<usize>>::new();
+    let mut sink = CollectSink {
+      feeds: vec![],
+      checkpoint_every: Some(2),
+      write_checkpoint: |feeds: &[Feed]| {
+        checkpoints.push(feeds.iter().map(|feed| feed.appid).collect());
+        Ok(())
+      },
+    };
+    for appid in 1..=5 {
+      sink.write_feed(&Feed::new(appid, appid.to_string()))?;
+    }
+    sink.finish()?;
+
+    assert_eq!(sink.feeds.len(), 5);
+    assert_eq!(checkpoints, [vec![1, 2], vec![1, 2, 3, 4]]);
+    Ok(())
   }
 
-  Ok(())
-}
+  #[test]
+  fn moved_feeds_are_found_in_the_history() {
+    let verified = |appid, url: &str| Feed {
+      verification: Some(VerificationStatus::Valid),
+      ..Feed::new(appid, url.to_string())
+    };
+    let mut history = BTreeMap::new();
+
+    let first_run = [
+      verified(440, "https://a/440"),
+      verified(570, "https://a/570"),
+    ];
+    assert!(update_history(&mut history, &first_run).is_empty());
+
+    let second_run = [
+      verified(440, "https://b/440"),
+      verified(570, "https://a/570"),
+      Feed::new(730, "https://a/730".to_string()),
+    ];
+    let moved = update_history(&mut history, &second_run)
+      .into_iter()
+      .map(|(feed, previous_url)| (feed.appid, previous_url))
+      .collect::<Vec<_>>();
+    assert_eq!(moved, [(440, "https://a/440".to_string())]);
+
+    // Unverified feeds aren't recorded, since they may not exist at all.
+    assert_eq!(
+      history.into_iter().collect::<Vec<_>>(),
+      [
+        (440, "https://b/440".to_string()),
+        (570, "https://a/570".to_string())
+      ]
+    );
+  }
 
-/// Creates a Steam RSS URL from a given AppID.
-fn appid_to_rss_url<D: std::fmt::Display>(appid: D) -> String {
-  format!("https://steamcommunity.com/games/{appid}/rss/")
-}
+  #[test]
+  fn templates_are_rendered_with_every_feed() -> Result<()> {
+    let feeds = [
+      Feed {
+        title: Some("Team Fortress 2".to_string()),
+        ..Feed::new(440, "https://a/440".to_string())
+      },
+      Feed::new(570, "https://a/570".to_string()),
+    ];
+    let context = TemplateContext {
+      count: feeds.len(),
+      feeds: &feeds,
+      generated_at: 0,
+      skipped: 1,
+      version: "1.0.0",
+    };
+
+    let rendered = render_template(
+      "{count} feeds, {skipped} skipped:\n\
+       {{ for feed in feeds }}{feed.appid} {feed.url} {feed.title}\n{{ endfor }}",
+      &context,
+    )?;
+    assert_eq!(
+      rendered,
+      "2 feeds, 1 skipped:\n440 https://a/440 Team Fortress 2\n\
+       570 https://a/570 \n"
+    );
+
+    assert!(render_template("{{ for feed in feeds }}", &context).is_err());
+    assert!(render_template("{missing}", &context).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn low_success_rates_fail() {
+    let summary = |verified, valid| Summary {
+      verified,
+      valid,
+      ..Summary::default()
+    };
 
-/// Creates a user's Steam Games URL from a given User ID.
-fn userid_to_games_url<D: std::fmt::Display>(userid: D) -> String {
-  format!("https://steamcommunity.com/id/{userid}/games/?tab=all")
+    assert!(check_success_rate(&summary(4, 3), 75).is_ok());
+    assert!(check_success_rate(&summary(0, 0), 100).is_ok());
+    assert!(check_success_rate(&summary(3, 0), 0).is_ok());
+    let error = check_success_rate(&summary(3, 2), 75).unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      "Only 66.7% of 3 verified feeds were valid (--min-success-rate 75)."
+    );
+  }
+
+  #[test]
+  fn stdin_tasks_are_parsed() -> Result<()> {
+    let tasks = parse_stdin_tasks(
+      r#"[
+        {"kind": "appid", "value": 440},
+        {"kind": "url", "value": "https://store.steampowered.com/app/570/"},
+        {"kind": "user", "value": "Bauke"}
+      ]"#,
+    )?;
+    assert_eq!(
+      tasks,
+      [
+        StdinTask::Appid(440),
+        StdinTask::Url("https://store.steampowered.com/app/570/".to_string()),
+        StdinTask::User("Bauke".to_string()),
+      ]
+    );
+    assert!(parse_stdin_tasks("[]")?.is_empty());
+    Ok(())
+  }
+
+  #[test]
+  fn malformed_stdin_tasks_are_reported_by_index() {
+    let error = parse_stdin_tasks(
+      r#"[
+        {"kind": "appid", "value": 440},
+        {"kind": "appid", "value": "not a number"},
+        {"kind": "group", "value": "friends"},
+        "user"
+      ]"#,
+    )
+    .unwrap_err()
+    .to_string();
+
+    let lines = error.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "Malformed tasks on stdin (--stdin-json):");
+    assert_eq!(
+      lines[1..]
+        .iter()
+        .map(|line| line.split(':').next().unwrap())
+        .collect::<Vec<_>>(),
+      ["1", "2", "3"]
+    );
+
+    let error = parse_stdin_tasks(r#"{"kind": "appid"}"#).unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      "Couldn't parse stdin as a JSON array (--stdin-json)"
+    );
+  }
 }
@@ -0,0 +1,1777 @@
+//! Finding the feeds for the inputs from [`Args`], by scanning users' games
+//! and verifying the potential feeds.
+
+use std::{
+  cell::RefCell,
+  collections::{hash_map::Entry, HashMap, HashSet},
+  fs::read_to_string,
+  num::NonZeroUsize,
+  sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    mpsc::channel,
+  },
+  thread::{scope, sleep},
+  time::{Duration, Instant},
+};
+
+use {
+  color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+  },
+  serde::Serialize,
+  serde_json::Value,
+};
+
+use crate::{
+  args::{FeedSource, TextSource},
+  date,
+  http::{is_timeout, Client, RequestBuilder, StatusError},
+  steam::{
+    appid_to_details_url, appid_to_hub_url, appid_to_rss_url,
+    appid_to_store_rss_url, call_steam_api, find_element_text, is_active,
+    is_age_gate, is_ambiguous_user, parse_games, parse_user,
+    steamid_to_games_url, strip_bbcode, titles_match, unescape_html, UserRef,
+    HUB_FRIENDLY_URL_REGEX, STORE_URL_REGEX, USER_NAME_REGEX,
+    USER_STEAMID_REGEX, USER_URL_REGEX,
+  },
+  Args, Feed, FeedSink, VerificationStatus,
+};
+
+/// An input that didn't result in a feed.
+#[derive(Debug, Serialize)]
+pub struct SkippedInput {
+  /// The input as it was given, or the URL it resolved to.
+  pub input: String,
+
+  /// Why the input was skipped.
+  pub reason: SkipReason,
+}
+
+impl SkippedInput {
+  /// Creates a new skipped input.
+  pub fn new<D: std::fmt::Display>(input: D, reason: SkipReason) -> Self {
+    Self {
+      input: input.to_string(),
+      reason,
+    }
+  }
+}
+
+/// The reasons an input can be skipped, serialized as machine-readable codes
+/// like `private_profile`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+  /// The feed is behind Steam's age check, even when sending the cookies that
+  /// should skip it.
+  AgeGated,
+
+  /// The input was already given before, with `--input-dedupe`.
+  Duplicate,
+
+  /// The feed has no news from within `--active-within`.
+  Inactive,
+
+  /// The feed didn't return XML when verifying it.
+  InvalidFeed,
+
+  /// The user's games couldn't be scanned, most likely because they're
+  /// private.
+  PrivateProfile,
+
+  /// Scanning the user's games took longer than `--scan-timeout`.
+  ScanTimeout,
+
+  /// The `--url` isn't a Steam store URL.
+  UnmatchedUrl,
+
+  /// The `--user` isn't a User ID or steamcommunity.com URL.
+  UnmatchedUser,
+}
+
+/// How many feeds a scanned user contributed, for `--count-by-user`.
+#[derive(Debug, Eq, PartialEq)]
+pub struct UserCount {
+  /// The user's display name, or the URL of their games when it's unknown.
+  pub user: String,
+
+  /// How many feeds were found in the user's games.
+  pub feeds: usize,
+
+  /// How many of those weren't found in any earlier input.
+  pub new_feeds: usize,
+}
+
+/// What happened while resolving the feeds, besides the feeds themselves.
+#[derive(Debug, Default)]
+pub struct Summary {
+  /// How many user scans and feed verifications failed.
+  pub failures: usize,
+
+  /// Every input that didn't result in a feed.
+  pub skipped: Vec<SkippedInput>,
+
+  /// The URLs of the feeds that took longer than `--warn-slow-feeds` to
+  /// verify.
+  pub slow_feeds: Vec<String>,
+
+  /// How many feeds every scanned user contributed.
+  pub user_counts: Vec<UserCount>,
+
+  /// How many feeds were valid when verifying them.
+  pub valid: usize,
+
+  /// How many feeds were verified.
+  pub verified: usize,
+}
+
+/// Finds the feeds for every input in `args` and writes them to `sink`, in
+/// the order of the inputs.
+///
+/// With [`Args::verifies`] every feed is verified before it's written and
+/// invalid ones are left out. The text options are applied to every feed.
+pub fn resolve(
+  args: &Args,
+  client: &Client,
+  sink: &mut dyn FeedSink,
+) -> Result<Summary> {
+  let verify = args.verifies();
+  let scan_timeout = args.scan_timeout.map(Duration::from_millis);
+  let preferred = args.prefer.unwrap_or(FeedSource::Community);
+  let Inputs {
+    feeds: mut potential_feeds,
+    skipped,
+    mut user_urls,
+  } = parse_inputs(args)?;
+  let mut summary = Summary {
+    skipped,
+    ..Summary::default()
+  };
+
+  // The friends' games are put in a folder per friend by default, the users
+  // that were given directly only get one with `--group-name-template`.
+  let first_friend = user_urls.len();
+  if args.users_from_friends.is_some() {
+    let friends = friends_to_steamids(client, args, &mut summary.skipped)?;
+    for friend in friends {
+      let friend_url = steamid_to_games_url(&friend);
+      if args.input_dedupe && user_urls.contains(&friend_url) {
+        summary
+          .skipped
+          .push(SkippedInput::new(friend, SkipReason::Duplicate));
+        continue;
+      }
+
+      user_urls.push(friend_url);
+    }
+  }
+
+  let rename_map = match &args.rename_map {
+    Some(path) => parse_rename_map(&read_to_string(path)?)
+      .wrap_err_with(|| format!("Couldn't parse {}", path.display()))?,
+    None => HashMap::new(),
+  };
+
+  // Applies the text processing options to a feed and writes it to the sink.
+  let mut write_feed = |mut feed: Feed| -> Result<()> {
+    // Empty titles are treated as missing unless explicitly accepted.
+    let title = feed.title.clone().filter(|title| {
+      args.verify_accept_empty_title || !title.trim().is_empty()
+    });
+    let appid_text = || format!("Steam AppID {}", feed.appid);
+    feed.text = Some(match args.opml_text_from {
+      TextSource::Verified => title
+        .or_else(|| feed.name.clone())
+        .unwrap_or_else(appid_text),
+      TextSource::Scraped => {
+        feed.name.clone().or(title).unwrap_or_else(appid_text)
+      }
+      TextSource::Appid => appid_text(),
+    });
+
+    if args.strip_bbcode {
+      for text in [&mut feed.text, &mut feed.source_user]
+        .into_iter()
+        .flatten()
+      {
+        *text = strip_bbcode(text).into_owned();
+      }
+    }
+
+    let renamed = rename_map
+      .get(&feed.appid.to_string())
+      .or_else(|| rename_map.get(&feed.url));
+    if let Some(renamed) = renamed {
+      feed.text = Some(renamed.clone());
+    }
+
+    if args.only_verified && feed.verification.is_none() {
+      bail!("Refusing to output unverified feed: {}", feed.url);
+    }
+
+    sink.write_feed(&feed)
+  };
+
+  let max_failures = args.max_failures.map_or(usize::MAX, NonZeroUsize::get);
+
+  // The AppIDs found so far, used by `--count-by-user`.
+  let mut found_appids = potential_feeds
+    .iter()
+    .map(|feed: &Feed| feed.appid)
+    .collect::<HashSet<_>>();
+
+  // Scans a user's games page, returning `None` when it took longer than
+  // `--scan-timeout`. Those requests are abandoned so the remaining users can
+  // still be scanned, other errors still fail.
+  let scan_user = |user_url: &str| -> Result<Option<String>> {
+    let mut request = client.get(user_url);
+    if let Some(scan_timeout) = scan_timeout {
+      request = request.timeout(scan_timeout);
+    }
+
+    let body = request
+      .call()
+      .and_then(|response| client.read_body(response));
+    sleep(client.delay);
+
+    match body {
+      Err(error) if scan_timeout.is_some() && is_timeout(&error) => Ok(None),
+      body => body.map(Some),
+    }
+  };
+
+  // Fetch the user game pages with a pool of `--max-concurrent-users`
+  // workers, while processing them in the order they were given. Once the
+  // processing stops early the receiver is dropped, which stops the workers
+  // from starting any more scans.
+  let next_user = AtomicUsize::new(0);
+  let workers = args.max_concurrent_users.get().min(user_urls.len());
+  scope(|scope| -> Result<()> {
+    let (sender, receiver) = channel();
+    for _ in 0..workers {
+      let (sender, next_user, scan_user, user_urls) =
+        (sender.clone(), &next_user, &scan_user, &user_urls);
+      scope.spawn(move || {
+        let mut index = next_user.fetch_add(1, Ordering::Relaxed);
+        while let Some(user_url) = user_urls.get(index) {
+          if sender.send((index, scan_user(user_url))).is_err() {
+            break;
+          }
+
+          index = next_user.fetch_add(1, Ordering::Relaxed);
+        }
+      });
+    }
+    drop(sender);
+
+    let mut scanned = HashMap::new();
+    for (index, user_url) in user_urls.iter().enumerate() {
+      let body = loop {
+        if let Some(body) = scanned.remove(&index) {
+          break body;
+        }
+
+        let (scanned_index, body) = receiver.recv()?;
+        scanned.insert(scanned_index, body);
+      };
+
+      let Some(body) = body? else {
+        eprintln!("Scanning took too long, skipping: {user_url}");
+        summary
+          .skipped
+          .push(SkippedInput::new(user_url, SkipReason::ScanTimeout));
+        summary.failures += 1;
+        if summary.failures >= max_failures {
+          break;
+        }
+
+        continue;
+      };
+
+      let user_name = USER_NAME_REGEX
+        .captures(&body)
+        .and_then(|captures| captures.name("name"))
+        .map(|name| unescape_html(name.as_str()));
+      let group_name_template = args
+        .group_name_template
+        .as_deref()
+        .or((index >= first_friend).then_some("{name}"));
+      let group = group_name_template.map(|template| {
+        let captures = USER_URL_REGEX.captures(user_url);
+        let capture = |name| {
+          captures
+            .as_ref()
+            .and_then(|captures| captures.name(name))
+            .map(|capture| capture.as_str())
+        };
+        let steamid = capture("steamid").or_else(|| {
+          USER_STEAMID_REGEX
+            .captures(&body)
+            .and_then(|captures| captures.name("steamid"))
+            .map(|steamid| steamid.as_str())
+        });
+        let user = capture("userid").or(steamid).unwrap_or(user_url);
+
+        template
+          .replace("{user}", user)
+          .replace("{name}", user_name.as_deref().unwrap_or(user))
+          .replace("{steamid}", steamid.unwrap_or(user))
+      });
+      if let Some(games) = parse_games(&body) {
+        let games = games
+          .wrap_err_with(|| format!("Couldn't parse games from: {user_url}"))?;
+        let game_count = games.len();
+        let mut new_count = 0;
+        for game in games {
+          if found_appids.insert(game.appid) {
+            new_count += 1;
+          }
+
+          let friendly_url = if game.friendly_url.is_string() {
+            Some(appid_to_rss_url(game.friendly_url.as_str().unwrap()))
+          } else {
+            None
+          };
+
+          potential_feeds.push(Feed {
+            friendly_url,
+            group: group.clone(),
+            icon: game.logo,
+            source_user: user_name.clone(),
+            name: Some(game.name),
+            ..Feed::new(game.appid, feed_url(game.appid, preferred))
+          });
+        }
+
+        summary.user_counts.push(UserCount {
+          user: user_name.as_deref().unwrap_or(user_url).to_string(),
+          feeds: game_count,
+          new_feeds: new_count,
+        });
+      } else if args.abort_on_private {
+        bail!(
+          "Couldn't scan games from: {user_url}\n\
+           Make sure \"Game Details\" in Privacy Settings is set to Public."
+        );
+      } else {
+        eprintln!("Couldn't scan games from: {user_url}");
+        if client.cookie.is_some() {
+          eprintln!("Your Steam session cookie may have expired.");
+        }
+        eprintln!(
+          "Make sure \"Game Details\" in Privacy Settings is set to Public."
+        );
+        summary
+          .skipped
+          .push(SkippedInput::new(user_url, SkipReason::PrivateProfile));
+        summary.failures += 1;
+        if summary.failures >= max_failures {
+          break;
+        }
+
+        continue;
+      }
+    }
+
+    Ok(())
+  })?;
+
+  // Feeds can't be output unverified when `--verify` is used, so when the
+  // scanning already failed too often there is nothing left to verify.
+  if verify && summary.failures < max_failures {
+    let slow_feed_threshold = args.warn_slow_feeds.map(Duration::from_millis);
+    let slow_feeds = RefCell::new(vec![]);
+    let cache_bust_counter = AtomicU64::new(0);
+    let verify_feed = |url: &str| -> Result<_> {
+      // The cache busting parameter is only added to the request, the feed
+      // keeps its original URL.
+      let request_url = if args.cache_bust {
+        let unique = date::now() as u64 * 1_000_000
+          + cache_bust_counter.fetch_add(1, Ordering::Relaxed);
+        cache_busted_url(url, unique)
+      } else {
+        url.to_string()
+      };
+
+      // Requests that fail, like when Steam is down or rate limiting, make the
+      // feed invalid so they count towards `--max-failures`.
+      let call = |request: RequestBuilder| {
+        request
+          .call()
+          .map_err(|error| eprintln!("Couldn't verify feed: {error}"))
+          .ok()
+      };
+
+      // Invalid feeds are HTML pages, so checking the content type with HEAD
+      // avoids downloading them. Servers that don't support HEAD just get the
+      // usual GET. Without a body, only age checks that redirect to
+      // `/agecheck/` can be recognized, others count as invalid feeds.
+      if args.verify_head_then_get {
+        let response = client.head(&request_url).call();
+        sleep(client.delay);
+        match response {
+          Ok(response) => {
+            if response.content_type != "text/xml"
+              && !response.url.contains("/agecheck/")
+            {
+              return Ok((false, String::new()));
+            }
+          }
+          Err(error)
+            if error
+              .downcast_ref::<StatusError>()
+              .is_some_and(|error| matches!(error.status, 405 | 501)) => {}
+          Err(error) => {
+            eprintln!("Couldn't verify feed: {error}");
+            return Ok((false, String::new()));
+          }
+        }
+      }
+
+      let start = Instant::now();
+      let Some(response) = call(client.get(&request_url)) else {
+        sleep(client.delay);
+        return Ok((false, String::new()));
+      };
+      let mut is_xml = response.content_type == "text/xml";
+      let mut body = client.read_body(response)?;
+      let mut elapsed = start.elapsed();
+      sleep(client.delay);
+
+      // Mature games show the age check instead of their feed, which can be
+      // skipped by sending the cookies it would set.
+      if !is_xml && is_age_gate(&body) {
+        let start = Instant::now();
+        if let Some(response) = call(client.get_past_age_gate(&request_url)) {
+          is_xml = response.content_type == "text/xml";
+          body = client.read_body(response)?;
+          elapsed += start.elapsed();
+        }
+        sleep(client.delay);
+      }
+
+      if slow_feed_threshold.is_some_and(|threshold| elapsed > threshold) {
+        eprintln!("Slow feed: {url} took {}ms", elapsed.as_millis());
+        slow_feeds.borrow_mut().push(url.to_string());
+      }
+
+      Ok((is_xml, body))
+    };
+
+    // The friendly URL names found through `--resolve-friendly-url`, so every
+    // AppID is only looked up once.
+    let mut resolved_friendly_urls = HashMap::<usize, Option<String>>::new();
+    let mut resolve_friendly_url = |appid: usize| -> Option<String> {
+      if let Some(friendly_url) = resolved_friendly_urls.get(&appid) {
+        return friendly_url.clone();
+      }
+
+      // A game's community hub links to its announcements using the same name
+      // as its feed. The friendly URL is only a fallback, so when the hub
+      // can't be fetched the feed is treated as not having one.
+      let hub_url = appid_to_hub_url(appid);
+      let body = client
+        .get(&hub_url)
+        .call()
+        .and_then(|response| client.read_body(response));
+      sleep(client.delay);
+
+      let friendly_url = match body {
+        Ok(body) => HUB_FRIENDLY_URL_REGEX
+          .captures(&body)
+          .and_then(|captures| captures.name("name"))
+          .map(|name| name.as_str())
+          .filter(|name| *name != appid.to_string())
+          .map(appid_to_rss_url),
+        Err(error) => {
+          eprintln!(
+            "Couldn't find the friendly URL for AppID {appid}: {error}"
+          );
+          None
+        }
+      };
+      resolved_friendly_urls.insert(appid, friendly_url.clone());
+      friendly_url
+    };
+
+    let verify_sample = args.verify_sample.unwrap_or(usize::MAX);
+    for (index, mut potential_feed) in potential_feeds.into_iter().enumerate() {
+      if index >= verify_sample {
+        write_feed(potential_feed)?;
+        continue;
+      }
+
+      let (mut is_valid_feed, mut body) = verify_feed(&potential_feed.url)?;
+      summary.verified += 1;
+
+      // Only try the other URL pattern when a preference was given, by default
+      // just the community feeds are used.
+      if !is_valid_feed && args.prefer.is_some() {
+        let alternate = match preferred {
+          FeedSource::Community => FeedSource::Store,
+          FeedSource::Store => FeedSource::Community,
+        };
+        let alternate_url = feed_url(potential_feed.appid, alternate);
+        (is_valid_feed, body) = verify_feed(&alternate_url)?;
+        if is_valid_feed {
+          potential_feed.url = alternate_url;
+          potential_feed.verification = Some(VerificationStatus::AlternateUsed);
+        }
+      }
+
+      if !is_valid_feed
+        && potential_feed.friendly_url.is_none()
+        && args.resolve_friendly_url
+      {
+        potential_feed.friendly_url =
+          resolve_friendly_url(potential_feed.appid);
+      }
+
+      // If the potential URL doesn't return `text/xml`, try the friendly URL
+      // if one exists.
+      if !is_valid_feed && potential_feed.friendly_url.is_some() {
+        let friendly_url = potential_feed.friendly_url.as_deref().unwrap();
+        (is_valid_feed, body) = verify_feed(friendly_url)?;
+        if is_valid_feed {
+          potential_feed.url = friendly_url.to_string();
+          potential_feed.verification = Some(VerificationStatus::FriendlyUsed);
+        }
+      }
+
+      if !is_valid_feed {
+        let reason = if is_age_gate(&body) {
+          eprintln!("Feed is behind Steam's age check: {}", potential_feed.url);
+          SkipReason::AgeGated
+        } else {
+          SkipReason::InvalidFeed
+        };
+        summary
+          .skipped
+          .push(SkippedInput::new(potential_feed.url, reason));
+        summary.failures += 1;
+        if summary.failures >= max_failures {
+          break;
+        }
+
+        continue;
+      }
+
+      summary.valid += 1;
+      potential_feed
+        .verification
+        .get_or_insert(VerificationStatus::Valid);
+
+      // Steam sometimes serves a different game's feed from the AppID URL,
+      // so when the title doesn't match the game's name see if the friendly
+      // URL has the right one.
+      let title_mismatches = |body: &str, name: Option<&str>| {
+        let title = find_element_text(body, "title").unwrap_or_default();
+        name.is_some_and(|name| !titles_match(title, name))
+      };
+      if args.revalidate_on_mismatch
+        && potential_feed.verification == Some(VerificationStatus::Valid)
+        && title_mismatches(&body, potential_feed.name.as_deref())
+      {
+        if potential_feed.friendly_url.is_none() {
+          potential_feed.friendly_url =
+            resolve_friendly_url(potential_feed.appid);
+        }
+
+        if let Some(friendly_url) = potential_feed.friendly_url.clone() {
+          let (is_valid_friendly_feed, friendly_body) =
+            verify_feed(&friendly_url)?;
+          if is_valid_friendly_feed
+            && !title_mismatches(&friendly_body, potential_feed.name.as_deref())
+          {
+            body = friendly_body;
+            potential_feed.url = friendly_url;
+            potential_feed.verification =
+              Some(VerificationStatus::FriendlyUsed);
+          }
+        }
+      }
+
+      potential_feed.title =
+        find_element_text(&body, "title").map(ToString::to_string);
+      potential_feed.last_build_date =
+        find_element_text(&body, "lastBuildDate").map(ToString::to_string);
+      potential_feed.age = potential_feed
+        .last_build_date
+        .as_deref()
+        .and_then(date::parse_rfc2822)
+        .map(|timestamp| date::format_age(timestamp, date::now()));
+
+      if let Some(active_within) = args.active_within {
+        if !is_active(&body, active_within, date::now()) {
+          summary
+            .skipped
+            .push(SkippedInput::new(potential_feed.url, SkipReason::Inactive));
+          continue;
+        }
+      }
+
+      write_feed(potential_feed)?;
+    }
+
+    summary.slow_feeds = slow_feeds.into_inner();
+  } else if !verify {
+    for feed in potential_feeds {
+      write_feed(feed)?;
+    }
+  }
+
+  sink.finish()?;
+  Ok(summary)
+}
+
+/// The inputs from [`Args`] that were recognized, before any of them are
+/// requested.
+#[derive(Debug, Default)]
+pub struct Inputs {
+  /// The potential feeds for the `--appid` and `--url` inputs.
+  pub feeds: Vec<Feed>,
+
+  /// The inputs that were skipped because they weren't recognized, or were
+  /// duplicates with `--input-dedupe`.
+  pub skipped: Vec<SkippedInput>,
+
+  /// The URLs of the games pages of the `--user` inputs.
+  pub user_urls: Vec<String>,
+}
+
+/// Recognizes the `--appid`, `--url` and `--user` inputs. With
+/// `--input-dedupe`, inputs that point to the same game or user as an earlier
+/// one are skipped, so nothing gets requested twice.
+pub fn parse_inputs(args: &Args) -> Result<Inputs> {
+  let preferred = args.prefer.unwrap_or(FeedSource::Community);
+  let mut inputs = Inputs::default();
+
+  // The AppIDs that have already been seen, the users are compared by the
+  // URL of their games instead.
+  let mut seen_appids = HashSet::new();
+  let store_url_appid = |url: &str| {
+    STORE_URL_REGEX
+      .captures(url)
+      .and_then(|captures| captures.name("appid"))
+      .and_then(|appid_match| appid_match.as_str().parse::<usize>().ok())
+  };
+  let appids = args
+    .appid
+    .iter()
+    .map(|appid| (appid.to_string(), Some(*appid)))
+    .chain(
+      args
+        .url
+        .iter()
+        .map(|url| (url.clone(), store_url_appid(url))),
+    );
+
+  for (input, appid) in appids {
+    let Some(appid) = appid else {
+      inputs
+        .skipped
+        .push(SkippedInput::new(input, SkipReason::UnmatchedUrl));
+      continue;
+    };
+
+    if args.input_dedupe && !seen_appids.insert(appid) {
+      inputs
+        .skipped
+        .push(SkippedInput::new(input, SkipReason::Duplicate));
+      continue;
+    }
+
+    inputs
+      .feeds
+      .push(Feed::new(appid, feed_url(appid, preferred)));
+  }
+
+  for user in &args.user {
+    let user = user.trim();
+    let user_url = if is_ambiguous_user(user)
+      && resolve_as_steamid(user, args.interactive_resolve)?
+    {
+      steamid_to_games_url(user)
+    } else if let Some(user_ref) = parse_user(user) {
+      user_ref.games_url()
+    } else {
+      eprintln!("Couldn't find a User ID in: {user}");
+      inputs
+        .skipped
+        .push(SkippedInput::new(user, SkipReason::UnmatchedUser));
+      continue;
+    };
+
+    // Vanity URLs are case-insensitive so compare them that way too.
+    let is_duplicate = inputs
+      .user_urls
+      .iter()
+      .any(|seen_url| seen_url.eq_ignore_ascii_case(&user_url));
+    if args.input_dedupe && is_duplicate {
+      inputs
+        .skipped
+        .push(SkippedInput::new(user, SkipReason::Duplicate));
+      continue;
+    }
+
+    inputs.user_urls.push(user_url);
+  }
+
+  Ok(inputs)
+}
+
+/// Adds the `--cache-bust` query parameter with a `unique` value to a URL.
+pub fn cache_busted_url(url: &str, unique: u64) -> String {
+  let separator = if url.contains('?') { '&' } else { '?' };
+  format!("{url}{separator}steam-rss-cache-bust={unique}")
+}
+
+/// Creates the feed URL for an AppID using the pattern of `source`.
+fn feed_url(appid: usize, source: FeedSource) -> String {
+  match source {
+    FeedSource::Community => appid_to_rss_url(appid),
+    FeedSource::Store => appid_to_store_rss_url(appid),
+  }
+}
+
+/// Looks up the icons of the feeds that don't have one yet in the store, for
+/// `--fetch-icons`. Every AppID is only looked up once.
+pub fn fetch_icons(client: &Client, feeds: &mut [Feed]) {
+  let mut icon_urls = HashMap::<usize, Option<String>>::new();
+  for feed in feeds.iter_mut().filter(|feed| feed.icon.is_none()) {
+    if let Entry::Vacant(entry) = icon_urls.entry(feed.appid) {
+      let details_url = appid_to_details_url(feed.appid);
+      let details = client
+        .get(&details_url)
+        .call()
+        .and_then(|response| client.read_body(response))
+        .and_then(|details| Ok(serde_json::from_str::<Value>(&details)?));
+      sleep(client.delay);
+
+      // Icons are only cosmetic, so the feed is still output without one
+      // when the store is rate limiting or down.
+      let icon_url = match details {
+        Ok(details) => details[feed.appid.to_string()]["data"]["header_image"]
+          .as_str()
+          .map(ToString::to_string),
+        Err(error) => {
+          eprintln!(
+            "Couldn't fetch the icon for AppID {}: {error}",
+            feed.appid
+          );
+          None
+        }
+      };
+      entry.insert(icon_url);
+    }
+
+    feed.icon = icon_urls[&feed.appid].clone();
+  }
+}
+
+/// Decides whether an ambiguous `--user` is a SteamID64 instead of a vanity
+/// User ID. With `--interactive-resolve` and a terminal this asks which one
+/// it is, otherwise it's treated as a SteamID64.
+#[cfg(feature = "dialoguer")]
+fn resolve_as_steamid(user: &str, interactive: bool) -> Result<bool> {
+  use std::io::{stderr, stdin, IsTerminal};
+
+  if !interactive || !stdin().is_terminal() || !stderr().is_terminal() {
+    return Ok(true);
+  }
+
+  let choice = dialoguer::Select::new()
+    .with_prompt(format!("Is {user} a SteamID64 or a vanity User ID?"))
+    .items(["SteamID64", "Vanity User ID"])
+    .default(0)
+    .interact()?;
+  Ok(choice == 0)
+}
+
+/// Decides whether an ambiguous `--user` is a SteamID64 instead of a vanity
+/// User ID. Without the `dialoguer` feature it always is.
+#[cfg(not(feature = "dialoguer"))]
+fn resolve_as_steamid(_user: &str, _interactive: bool) -> Result<bool> {
+  Ok(true)
+}
+
+/// Parses the `AppID=Name` and `URL=Name` lines of a `--rename-map` file.
+/// Empty lines and lines starting with `#` are skipped.
+pub fn parse_rename_map(contents: &str) -> Result<HashMap<String, String>> {
+  let mut rename_map = HashMap::new();
+  for (index, line) in contents.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+
+    let Some((key, name)) = line.split_once('=') else {
+      bail!("Line {} is missing a \"=\": {line}", index + 1);
+    };
+
+    rename_map.insert(key.trim().to_string(), name.trim().to_string());
+  }
+
+  Ok(rename_map)
+}
+
+/// Finds the friends of the `--users-from-friends` user through the Steam Web
+/// API and returns the SteamID64s of the ones whose games can be scanned.
+/// Friends with a private profile are added to `skipped_inputs`.
+fn friends_to_steamids(
+  client: &Client,
+  args: &Args,
+  skipped_inputs: &mut Vec<SkippedInput>,
+) -> Result<Vec<String>> {
+  let user = args
+    .users_from_friends
+    .as_deref()
+    .unwrap_or_default()
+    .trim();
+  let api_key = args.steam_api_key.as_deref().unwrap_or_default();
+
+  let user_ref = if is_ambiguous_user(user)
+    && resolve_as_steamid(user, args.interactive_resolve)?
+  {
+    Some(UserRef::SteamId(user.to_string()))
+  } else {
+    parse_user(user)
+  };
+
+  let steamid = match user_ref {
+    Some(UserRef::SteamId(steamid)) => steamid,
+    Some(UserRef::UserId(vanity)) if vanity.is_ascii() => {
+      let resolved = call_steam_api(
+        client,
+        "ISteamUser/ResolveVanityURL/v1",
+        api_key,
+        &format!("vanityurl={vanity}"),
+      )?;
+      sleep(client.delay);
+
+      let Some(steamid) = resolved["response"]["steamid"]
+        .as_str()
+        .map(ToString::to_string)
+      else {
+        bail!("Couldn't find a SteamID64 for: {user}");
+      };
+      steamid
+    }
+    _ => bail!("Couldn't find a User ID in: {user}"),
+  };
+
+  // Steam refuses to return private friends lists.
+  let friends = call_steam_api(
+    client,
+    "ISteamUser/GetFriendList/v1",
+    api_key,
+    &format!("steamid={steamid}&relationship=friend"),
+  )
+  .wrap_err_with(|| format!("Couldn't get the friends list of {user}"))?;
+  sleep(client.delay);
+
+  let mut friends = friends["friendslist"]["friends"]
+    .as_array()
+    .into_iter()
+    .flatten()
+    .filter_map(|friend| friend["steamid"].as_str())
+    .map(ToString::to_string)
+    .collect::<Vec<_>>();
+  friends.truncate(args.max_friends.unwrap_or(usize::MAX));
+
+  // Private profiles can't be scanned, so ask which ones are public in
+  // batches of the 100 profiles the API allows at once.
+  let mut public_friends = HashSet::new();
+  for batch in friends.chunks(100) {
+    let summaries = call_steam_api(
+      client,
+      "ISteamUser/GetPlayerSummaries/v2",
+      api_key,
+      &format!("steamids={}", batch.join(",")),
+    )?;
+    sleep(client.delay);
+
+    let players = summaries["response"]["players"].as_array();
+    for player in players.into_iter().flatten() {
+      // A visibility state of 3 means the profile is public.
+      if player["communityvisibilitystate"] == 3 {
+        public_friends.extend(player["steamid"].as_str().map(String::from));
+      }
+    }
+  }
+
+  let (public, private): (Vec<_>, Vec<_>) = friends
+    .into_iter()
+    .partition(|friend| public_friends.contains(friend));
+  for friend in private {
+    eprintln!("Skipping friend with a private profile: {friend}");
+    skipped_inputs.push(SkippedInput::new(
+      steamid_to_games_url(friend),
+      SkipReason::PrivateProfile,
+    ));
+  }
+
+  Ok(public)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use super::*;
+  use crate::{
+    fixtures::{args, games_page, html, resolve as run, rss},
+    http::{Response, AGE_CHECK_COOKIES},
+    steam::userid_to_games_url,
+  };
+
+  #[test]
+  fn duplicate_inputs_are_skipped() -> Result<()> {
+    let inputs = parse_inputs(&args(&[
+      "--input-dedupe",
+      "--appid",
+      "440",
+      "--url",
+      "https://store.steampowered.com/app/440/Team_Fortress_2/",
+      "--user",
+      "Bauke",
+      "--user",
+      "https://steamcommunity.com/id/bauke/",
+      "--user",
+      " BAUKE ",
+      "--user",
+      "https://www.steamcommunity.com/profiles/76561198000000000",
+      "--user",
+      "76561198000000000",
+    ]))?;
+
+    assert_eq!(
+      inputs
+        .feeds
+        .iter()
+        .map(|feed| feed.appid)
+        .collect::<Vec<_>>(),
+      [440]
+    );
+    assert_eq!(
+      inputs.user_urls,
+      [
+        userid_to_games_url("Bauke"),
+        steamid_to_games_url("76561198000000000")
+      ]
+    );
+    assert_eq!(
+      inputs
+        .skipped
+        .iter()
+        .map(|skipped| (skipped.input.as_str(), skipped.reason))
+        .collect::<Vec<_>>(),
+      [
+        (
+          "https://store.steampowered.com/app/440/Team_Fortress_2/",
+          SkipReason::Duplicate
+        ),
+        (
+          "https://steamcommunity.com/id/bauke/",
+          SkipReason::Duplicate
+        ),
+        ("BAUKE", SkipReason::Duplicate),
+        ("76561198000000000", SkipReason::Duplicate),
+      ]
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn duplicate_inputs_are_kept_without_dedupe() -> Result<()> {
+    let inputs = parse_inputs(&args(&[
+      "--appid", "440", "--appid", "440", "--user", "Bauke", "--user", "bauke",
+    ]))?;
+    assert_eq!(inputs.feeds.len(), 2);
+    assert_eq!(inputs.user_urls.len(), 2);
+    assert!(inputs.skipped.is_empty());
+    Ok(())
+  }
+
+  #[test]
+  fn user_passed_twice_is_scanned_once() -> Result<()> {
+    let (summary, feeds, requests) = run(
+      &["--input-dedupe", "--user", "Bauke", "--user", "bauke"],
+      |request| {
+        Ok(games_page(
+          request,
+          "Bauke",
+          r#"[{"appid":440,"name":"Team Fortress 2"}]"#,
+        ))
+      },
+    );
+
+    assert_eq!(summary?.skipped.len(), 1);
+    assert_eq!(feeds.len(), 1);
+    assert_eq!(requests.len(), 1);
+    Ok(())
+  }
+
+  #[test]
+  fn slow_feeds_are_recorded() -> Result<()> {
+    let (summary, feeds, _) = run(
+      &[
+        "--verify",
+        "--warn-slow-feeds",
+        "20",
+        "--appid",
+        "440",
+        "--appid",
+        "570",
+      ],
+      |request| {
+        if request.url.contains("/440/") {
+          sleep(Duration::from_millis(50));
+        }
+        Ok(rss(request, "Game", &[]))
+      },
+    );
+
+    assert_eq!(summary?.slow_feeds, [appid_to_rss_url(440)]);
+    assert_eq!(feeds.len(), 2);
+    Ok(())
+  }
+
+  #[test]
+  fn user_scans_respect_the_concurrency_cap() -> Result<()> {
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let most_in_flight = Arc::new(AtomicUsize::new(0));
+    let (counter, most) = (in_flight.clone(), most_in_flight.clone());
+    let users = (1..=6)
+      .map(|user| format!("user{user}"))
+      .collect::<Vec<_>>();
+    let mut arguments = vec!["--max-concurrent-users", "2"];
+    for user in &users {
+      arguments.extend(["--user", user]);
+    }
+
+    let (summary, feeds, requests) = run(&arguments, move |request| {
+      let current = counter.fetch_add(1, Ordering::SeqCst) + 1;
+      most.fetch_max(current, Ordering::SeqCst);
+
+      // Make the earlier users take longer so they finish out of order.
+      let appid = request.url.len();
+      sleep(Duration::from_millis(100 - appid as u64));
+      counter.fetch_sub(1, Ordering::SeqCst);
+
+      let games = format!(r#"[{{"appid":{appid},"name":"Game"}}]"#);
+      Ok(games_page(request, "User", &games))
+    });
+
+    assert_eq!(summary?.user_counts.len(), 6);
+    assert_eq!(requests.len(), 6);
+    assert_eq!(most_in_flight.load(Ordering::SeqCst), 2);
+
+    // The feeds are still in the order the users were given.
+    let expected = users
+      .iter()
+      .map(|user| userid_to_games_url(user).len())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      feeds.iter().map(|feed| feed.appid).collect::<Vec<_>>(),
+      expected
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn private_profiles_abort_in_strict_mode() {
+    let (summary, feeds, _) =
+      run(&["--abort-on-private", "--user", "Bauke"], |request| {
+        Ok(html(request, "<html></html>"))
+      });
+
+    let error = summary.unwrap_err().to_string();
+    assert!(error.starts_with(&format!(
+      "Couldn't scan games from: {}",
+      userid_to_games_url("Bauke")
+    )));
+    assert!(feeds.is_empty());
+  }
+
+  #[test]
+  fn private_profiles_are_skipped_by_default() -> Result<()> {
+    let (summary, _, _) = run(&["--user", "Bauke"], |request| {
+      Ok(html(request, "<html></html>"))
+    });
+
+    let summary = summary?;
+    assert_eq!(summary.skipped[0].reason, SkipReason::PrivateProfile);
+    assert_eq!(summary.failures, 1);
+    Ok(())
+  }
+
+  #[test]
+  fn slow_user_scans_are_abandoned() -> Result<()> {
+    let (summary, feeds, requests) = run(
+      &["--scan-timeout", "50", "--user", "slow", "--user", "fast"],
+      |request| {
+        if request.url.contains("/slow/") {
+          return Err(
+            std::io::Error::from(std::io::ErrorKind::TimedOut).into(),
+          );
+        }
+
+        Ok(games_page(
+          request,
+          "Fast",
+          r#"[{"appid":440,"name":"Team Fortress 2"}]"#,
+        ))
+      },
+    );
+
+    let summary = summary?;
+    assert_eq!(summary.skipped.len(), 1);
+    assert_eq!(summary.skipped[0].input, userid_to_games_url("slow"));
+    assert_eq!(summary.skipped[0].reason, SkipReason::ScanTimeout);
+    assert_eq!(feeds.len(), 1);
+    assert!(requests
+      .iter()
+      .all(|request| request.timeout == Some(Duration::from_millis(50))));
+    Ok(())
+  }
+
+  #[test]
+  fn friendly_urls_are_found_on_the_hub() -> Result<()> {
+    let (summary, feeds, requests) = run(
+      &[
+        "--verify",
+        "--resolve-friendly-url",
+        "--appid",
+        "440",
+        "--appid",
+        "570",
+        "--appid",
+        "570",
+      ],
+      |request| match request.url.as_str() {
+        "https://steamcommunity.com/app/440" => Ok(html(
+          request,
+          r#"<a href="https://steamcommunity.com/games/TF2/announcements/">"#,
+        )),
+        "https://steamcommunity.com/app/570" => bail!("Connection reset"),
+        "https://steamcommunity.com/games/TF2/rss/" => {
+          Ok(rss(request, "Team Fortress 2", &[]))
+        }
+        _ => Ok(html(request, "<html></html>")),
+      },
+    );
+
+    // The hub that couldn't be fetched only makes its feed invalid, and isn't
+    // requested again for the same AppID.
+    let summary = summary?;
+    assert_eq!(summary.skipped.len(), 2);
+    assert_eq!(
+      requests
+        .iter()
+        .filter(|request| request.url == appid_to_hub_url(570))
+        .count(),
+      1
+    );
+
+    assert_eq!(feeds.len(), 1);
+    assert_eq!(feeds[0].url, appid_to_rss_url("TF2"));
+    assert_eq!(
+      feeds[0].verification,
+      Some(VerificationStatus::FriendlyUsed)
+    );
+    assert_eq!(feeds[0].title.as_deref(), Some("Team Fortress 2"));
+    Ok(())
+  }
+
+  #[test]
+  fn verification_stops_after_max_failures() -> Result<()> {
+    let (summary, feeds, requests) = run(
+      &[
+        "--verify",
+        "--max-failures",
+        "2",
+        "--appid",
+        "1",
+        "--appid",
+        "2",
+        "--appid",
+        "440",
+        "--appid",
+        "3",
+      ],
+      |request| Ok(html(request, "<html></html>")),
+    );
+
+    let summary = summary?;
+    assert_eq!((summary.failures, summary.verified), (2, 2));
+    assert_eq!(requests.len(), 2);
+    assert!(feeds.is_empty());
+    Ok(())
+  }
+
+  #[test]
+  fn scanning_stops_after_max_failures() -> Result<()> {
+    let (summary, feeds, _) = run(
+      &[
+        "--verify",
+        "--max-failures",
+        "2",
+        "--user",
+        "first",
+        "--user",
+        "second",
+        "--user",
+        "third",
+      ],
+      |request| Ok(html(request, "<html></html>")),
+    );
+
+    // No feeds are verified once the scans already failed too often.
+    let summary = summary?;
+    assert_eq!((summary.failures, summary.verified), (2, 0));
+    assert_eq!(summary.skipped.len(), 2);
+    assert!(feeds.is_empty());
+    Ok(())
+  }
+
+  #[test]
+  fn only_verified_feeds_are_output() -> Result<()> {
+    let (summary, feeds, _) = run(
+      &["--only-verified", "--appid", "440", "--appid", "10"],
+      |request| {
+        Ok(if request.url.contains("/10/") {
+          html(request, "<html></html>")
+        } else {
+          rss(request, "Team Fortress 2", &[])
+        })
+      },
+    );
+
+    assert_eq!(summary?.verified, 2);
+    assert_eq!(feeds.len(), 1);
+    assert_eq!(feeds[0].verification, Some(VerificationStatus::Valid));
+    Ok(())
+  }
+
+  #[test]
+  fn feeds_are_counted_by_user() -> Result<()> {
+    let (summary, feeds, _) = run(
+      &["--appid", "10", "--user", "alice", "--user", "bob"],
+      |request| {
+        Ok(if request.url.contains("/alice/") {
+          games_page(
+            request,
+            "Alice",
+            r#"[{"appid":10,"name":"Counter-Strike"},{"appid":440,"name":"TF2"}]"#,
+          )
+        } else {
+          games_page(
+            request,
+            "Bob",
+            r#"[{"appid":440,"name":"TF2"},{"appid":570,"name":"Dota 2"}]"#,
+          )
+        })
+      },
+    );
+
+    let count = |user: &str, feeds, new_feeds| UserCount {
+      user: user.to_string(),
+      feeds,
+      new_feeds,
+    };
+    assert_eq!(
+      summary?.user_counts,
+      [count("Alice", 2, 1), count("Bob", 2, 1)]
+    );
+    assert_eq!(feeds.len(), 5);
+    Ok(())
+  }
+
+  #[test]
+  fn feed_texts_come_from_the_selected_source() -> Result<()> {
+    for (source, expected) in [
+      ("verified", ["Steam AppID 10", "TF2 News", "Dota 2"]),
+      ("scraped", ["Steam AppID 10", "TF2", "Dota 2"]),
+      (
+        "appid",
+        ["Steam AppID 10", "Steam AppID 440", "Steam AppID 570"],
+      ),
+    ] {
+      let (_, feeds, _) = run(
+        &[
+          "--verify",
+          "--opml-text-from",
+          source,
+          "--appid",
+          "10",
+          "--user",
+          "alice",
+        ],
+        |request| {
+          Ok(if request.url.contains("/alice/") {
+            games_page(
+              request,
+              "Alice",
+              r#"[{"appid":440,"name":"TF2"},{"appid":570,"name":"Dota 2"}]"#,
+            )
+          } else if request.url.contains("/440/") {
+            rss(request, "TF2 News", &[])
+          } else {
+            rss(request, " ", &[])
+          })
+        },
+      );
+
+      let texts = feeds
+        .iter()
+        .map(|feed| feed.text.as_deref().unwrap())
+        .collect::<Vec<_>>();
+      assert_eq!(texts, expected, "{source}");
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn unparsable_games_fail_with_the_user_url() {
+    let (summary, _, _) = run(&["--user", "alice"], |request| {
+      Ok(games_page(request, "Alice", r#"[{"name":"No AppID"}]"#))
+    });
+
+    let error = summary.unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      format!(
+        "Couldn't parse games from: {}",
+        userid_to_games_url("alice")
+      )
+    );
+    assert!(error.root_cause().to_string().starts_with("missing field"));
+  }
+
+  #[test]
+  fn rename_maps_are_parsed() {
+    let rename_map = parse_rename_map(
+      "# A comment\n\n440 = Team Fortress 2\n\
+       https://steamcommunity.com/games/570/rss/=Dota = 2\n",
+    )
+    .unwrap();
+    assert_eq!(rename_map.len(), 2);
+    assert_eq!(rename_map["440"], "Team Fortress 2");
+    assert_eq!(
+      rename_map["https://steamcommunity.com/games/570/rss/"],
+      "Dota = 2"
+    );
+
+    let error = parse_rename_map("440=TF2\nmissing").unwrap_err();
+    assert!(error.to_string().starts_with("Line 2 "), "{error}");
+  }
+
+  #[test]
+  fn renamed_feeds_use_their_new_name() -> Result<()> {
+    let path = std::env::temp_dir()
+      .join(format!("steam-rss-rename-map-{}.txt", std::process::id()));
+    std::fs::write(
+      &path,
+      "440=TF2\nhttps://steamcommunity.com/games/570/rss/=Dota",
+    )?;
+    let (summary, feeds, _) = run(
+      &[
+        "--rename-map",
+        path.to_str().unwrap(),
+        "--appid",
+        "440",
+        "--appid",
+        "570",
+        "--appid",
+        "730",
+      ],
+      |request| bail!("Unexpected request: {}", request.url),
+    );
+    std::fs::remove_file(path)?;
+
+    summary?;
+    assert_eq!(
+      feeds
+        .iter()
+        .map(|feed| feed.text.as_deref().unwrap())
+        .collect::<Vec<_>>(),
+      ["TF2", "Dota", "Steam AppID 730"]
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn only_the_sample_is_verified() -> Result<()> {
+    let (summary, feeds, requests) = run(
+      &[
+        "--verify-sample",
+        "2",
+        "--appid",
+        "440",
+        "--appid",
+        "10",
+        "--appid",
+        "570",
+      ],
+      |request| {
+        Ok(if request.url.contains("/10/") {
+          html(request, "<html></html>")
+        } else {
+          rss(request, "Game", &[])
+        })
+      },
+    );
+
+    // The invalid feed in the sample is left out, the one after the sample is
+    // output without being requested.
+    assert_eq!(summary?.verified, 2);
+    assert_eq!(requests.len(), 2);
+    let verifications = feeds
+      .iter()
+      .map(|feed| (feed.appid, serde_json::to_value(feed).unwrap()))
+      .map(|(appid, feed)| (appid, feed["verification"].clone()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      verifications,
+      [(440, Value::from("valid")), (570, Value::Null)]
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn mismatched_feeds_are_revalidated() -> Result<()> {
+    let (_, feeds, requests) = run(
+      &["--verify", "--revalidate-on-mismatch", "--user", "alice"],
+      |request| {
+        Ok(match request.url.as_str() {
+          "https://steamcommunity.com/games/400/rss/" => {
+            rss(request, "Half-Life 2", &[])
+          }
+          "https://steamcommunity.com/games/Portal/rss/" => {
+            rss(request, "Portal", &[])
+          }
+          "https://steamcommunity.com/games/440/rss/" => {
+            rss(request, "Team Fortress 2", &[])
+          }
+          _ => games_page(
+            request,
+            "Alice",
+            concat!(
+              r#"[{"appid":400,"name":"Portal","friendlyURL":"Portal"},"#,
+              r#"{"appid":440,"name":"Team Fortress 2","friendlyURL":"tf2"}]"#,
+            ),
+          ),
+        })
+      },
+    );
+
+    let urls = feeds
+      .iter()
+      .map(|feed| (feed.url.as_str(), feed.verification.unwrap()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      urls,
+      [
+        (
+          "https://steamcommunity.com/games/Portal/rss/",
+          VerificationStatus::FriendlyUsed
+        ),
+        (
+          "https://steamcommunity.com/games/440/rss/",
+          VerificationStatus::Valid
+        ),
+      ]
+    );
+    assert_eq!(feeds[0].title.as_deref(), Some("Portal"));
+
+    // The feed with the right title doesn't need its friendly URL.
+    assert!(!requests.iter().any(|request| request.url.contains("/tf2/")));
+    Ok(())
+  }
+
+  #[test]
+  fn skipped_inputs_have_a_reason() -> Result<()> {
+    let (summary, feeds, _) = run(
+      &[
+        "--verify",
+        "--appid",
+        "10",
+        "--appid",
+        "20",
+        "--url",
+        "https://example.com/app/440",
+        "--user",
+        "not a user",
+        "--user",
+        "private",
+      ],
+      |request| {
+        Ok(if request.url.contains("/20/") {
+          html(request, r#"<div id="app_agegate"></div>"#)
+        } else {
+          html(request, "<html></html>")
+        })
+      },
+    );
+
+    assert!(feeds.is_empty());
+    let skipped = serde_json::to_value(summary?.skipped)?;
+    assert_eq!(
+      skipped,
+      serde_json::json!([
+        {"input": "https://example.com/app/440", "reason": "unmatched_url"},
+        {"input": "not a user", "reason": "unmatched_user"},
+        {
+          "input": userid_to_games_url("private"),
+          "reason": "private_profile"
+        },
+        {"input": appid_to_rss_url(10), "reason": "invalid_feed"},
+        {"input": appid_to_rss_url(20), "reason": "age_gated"},
+      ])
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn preferred_feed_urls_are_tried_first() -> Result<()> {
+    let (_, feeds, requests) = run(
+      &[
+        "--verify", "--prefer", "store", "--appid", "440", "--appid", "570",
+      ],
+      |request| {
+        Ok(if request.url == appid_to_store_rss_url(570) {
+          html(request, "<html></html>")
+        } else {
+          rss(request, "Game", &[])
+        })
+      },
+    );
+
+    assert_eq!(
+      requests
+        .iter()
+        .map(|request| request.url.clone())
+        .collect::<Vec<_>>(),
+      [
+        appid_to_store_rss_url(440),
+        appid_to_store_rss_url(570),
+        appid_to_rss_url(570),
+      ]
+    );
+    assert_eq!(
+      feeds
+        .iter()
+        .map(|feed| (feed.url.clone(), feed.verification.unwrap()))
+        .collect::<Vec<_>>(),
+      [
+        (appid_to_store_rss_url(440), VerificationStatus::Valid),
+        (appid_to_rss_url(570), VerificationStatus::AlternateUsed),
+      ]
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn cache_busting_only_changes_the_requested_url() -> Result<()> {
+    assert_eq!(
+      cache_busted_url("https://steamcommunity.com/games/440/rss/", 1),
+      "https://steamcommunity.com/games/440/rss/?steam-rss-cache-bust=1"
+    );
+    assert_eq!(
+      cache_busted_url("https://example.com/feed?page=2", 3),
+      "https://example.com/feed?page=2&steam-rss-cache-bust=3"
+    );
+
+    let (_, feeds, requests) = run(
+      &[
+        "--verify",
+        "--cache-bust",
+        "--appid",
+        "440",
+        "--appid",
+        "570",
+      ],
+      |request| Ok(rss(request, "Game", &[])),
+    );
+    let (first, second) = (&requests[0].url, &requests[1].url);
+    assert!(
+      first.starts_with(&format!("{}?steam-rss-cache-bust=", feeds[0].url))
+    );
+    assert!(
+      second.starts_with(&format!("{}?steam-rss-cache-bust=", feeds[1].url))
+    );
+    assert_ne!(first.rsplit('=').next(), second.rsplit('=').next());
+    assert_eq!(feeds[0].url, appid_to_rss_url(440));
+    Ok(())
+  }
+
+  #[test]
+  fn ambiguous_users_are_steamids_when_not_asked() -> Result<()> {
+    let mut cases = vec![&["--user", "76561197960287930"][..]];
+
+    // With the prompt available this would ask when the tests are run from a
+    // terminal, so only check the fallback without it.
+    if cfg!(not(feature = "dialoguer")) {
+      cases.push(&["--interactive-resolve", "--user", "76561197960287930"]);
+    }
+
+    for arguments in cases {
+      let inputs = parse_inputs(&args(arguments))?;
+      assert_eq!(
+        inputs.user_urls,
+        [steamid_to_games_url("76561197960287930")]
+      );
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn head_requests_skip_downloading_invalid_feeds() -> Result<()> {
+    let (summary, feeds, requests) = run(
+      &[
+        "--verify",
+        "--verify-head-then-get",
+        "--appid",
+        "440",
+        "--appid",
+        "10",
+        "--appid",
+        "570",
+      ],
+      |request| {
+        Ok(match (request.method, request.url.as_str()) {
+          ("HEAD", url) if url.contains("/570/") => {
+            Response::new(url, 405, "text/html", "")
+          }
+          (_, url) if url.contains("/10/") => html(request, ""),
+          _ => rss(request, "Game", &[]),
+        })
+      },
+    );
+
+    assert_eq!(summary?.valid, 2);
+    assert_eq!(
+      feeds.iter().map(|feed| feed.appid).collect::<Vec<_>>(),
+      [440, 570]
+    );
+    assert_eq!(
+      requests
+        .iter()
+        .map(|request| (request.method, request.url.clone()))
+        .collect::<Vec<_>>(),
+      [
+        ("HEAD", appid_to_rss_url(440)),
+        ("GET", appid_to_rss_url(440)),
+        ("HEAD", appid_to_rss_url(10)),
+        ("HEAD", appid_to_rss_url(570)),
+        ("GET", appid_to_rss_url(570)),
+      ]
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn age_gated_feeds_are_retried_with_cookies() -> Result<()> {
+    let (summary, feeds, requests) = run(
+      &[
+        "--verify",
+        "--cookie",
+        "steamLoginSecure=secret",
+        "--appid",
+        "440",
+      ],
+      |request| {
+        let cookie = request.header("Cookie").unwrap_or_default();
+        Ok(if cookie.contains("birthtime=0") {
+          rss(request, "Mature Game", &[])
+        } else {
+          html(request, r#"<div id="app_agegate"></div>"#)
+        })
+      },
+    );
+
+    assert_eq!(summary?.valid, 1);
+    assert_eq!(feeds[0].title.as_deref(), Some("Mature Game"));
+    assert_eq!(
+      requests[0].header("Cookie"),
+      Some("steamLoginSecure=secret")
+    );
+    assert_eq!(
+      requests[1].header("Cookie"),
+      Some(format!("steamLoginSecure=secret; {AGE_CHECK_COOKIES}").as_str())
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn friends_are_scanned_into_their_own_folders() -> Result<()> {
+    let (summary, feeds, requests) = run(
+      &[
+        "--steam-api-key",
+        "SECRET",
+        "--users-from-friends",
+        "76561197960287930",
+        "--max-friends",
+        "2",
+        "--user",
+        "alice",
+      ],
+      |request| {
+        let json = |body: &str| {
+          Response::new(&request.url, 200, "application/json", body.to_string())
+        };
+        Ok(if request.url.contains("/GetFriendList/") {
+          json(
+            r#"{"friendslist": {"friends": [
+              {"steamid": "76561190000000001"},
+              {"steamid": "76561190000000002"},
+              {"steamid": "76561190000000003"}
+            ]}}"#,
+          )
+        } else if request.url.contains("/GetPlayerSummaries/") {
+          json(
+            r#"{"response": {"players": [
+              {"steamid": "76561190000000001", "communityvisibilitystate": 3},
+              {"steamid": "76561190000000002", "communityvisibilitystate": 1}
+            ]}}"#,
+          )
+        } else if request.url.contains("/alice/") {
+          games_page(request, "Alice", r#"[{"appid":440,"name":"TF2"}]"#)
+        } else {
+          games_page(request, "Bob", r#"[{"appid":570,"name":"Dota 2"}]"#)
+        })
+      },
+    );
+
+    let summary = summary?;
+    assert_eq!(summary.skipped.len(), 1);
+    assert_eq!(
+      summary.skipped[0].input,
+      steamid_to_games_url("76561190000000002")
+    );
+    assert_eq!(summary.skipped[0].reason, SkipReason::PrivateProfile);
+
+    // Only the friends' feeds are put in a folder.
+    assert_eq!(
+      feeds
+        .iter()
+        .map(|feed| (feed.appid, feed.group.as_deref()))
+        .collect::<Vec<_>>(),
+      [(440, None), (570, Some("Bob"))]
+    );
+
+    let summaries_request = requests
+      .iter()
+      .find(|request| request.url.contains("/GetPlayerSummaries/"))
+      .unwrap();
+    assert!(summaries_request
+      .url
+      .ends_with("steamids=76561190000000001,76561190000000002"));
+    assert!(!requests
+      .iter()
+      .any(|request| request.url.contains("76561190000000003")));
+    Ok(())
+  }
+}
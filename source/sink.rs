@@ -0,0 +1,654 @@
+//! Output destinations for resolved feeds.
+
+use std::{
+  collections::HashSet,
+  io::{self, Write},
+};
+
+use {
+  color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+  },
+  serde_json::Value,
+};
+
+use crate::{diff::collect_feed_outlines, Feed, Format, LineEnding, Reader};
+
+/// A destination that resolved feeds are written to.
+///
+/// Implement this to handle feeds in a custom way while reusing the rest of
+/// the pipeline, the built-in implementations cover every [`Format`].
+pub trait FeedSink {
+  /// Writes a single feed to the sink.
+  fn write_feed(&mut self, feed: &Feed) -> Result<()>;
+
+  /// Finishes the output, this is called once after the last feed has been
+  /// written.
+  fn finish(&mut self) -> Result<()>;
+}
+
+/// Collects the feeds in memory.
+impl FeedSink for Vec<Feed> {
+  fn write_feed(&mut self, feed: &Feed) -> Result<()> {
+    self.push(feed.clone());
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<()> {
+    Ok(())
+  }
+}
+
+/// Creates the built-in sink for a [`Format`] that writes to `writer`. The
+/// `reader` is only used for [`Format::Script`].
+pub fn sink_for_format<'w, W: Write + 'w>(
+  format: Format,
+  reader: Reader,
+  writer: W,
+) -> Box<dyn FeedSink + 'w> {
+  match format {
+    Format::Urls => Box::new(UrlSink(writer)),
+    Format::Opml => Box::new(OpmlSink::new(writer)),
+    Format::Json => Box::new(JsonSink::new(writer)),
+    Format::Ndjson => Box::new(NdjsonSink(writer)),
+    Format::Csv => Box::new(CsvSink::new(writer)),
+    Format::Script => Box::new(ScriptSink::new(reader, writer)),
+    Format::Html => Box::new(HtmlSink::new(writer)),
+  }
+}
+
+/// Writes all `feeds` to `writer` using the built-in sink for `format`.
+///
+/// [`Format::Script`] always uses [`LineEnding::Lf`], since `/bin/sh` can't
+/// run scripts with `\r\n` line endings.
+pub fn write_feeds<W: Write>(
+  format: Format,
+  reader: Reader,
+  line_ending: LineEnding,
+  writer: W,
+  feeds: &[Feed],
+) -> Result<()> {
+  let line_ending = match format {
+    Format::Script => LineEnding::Lf,
+    _ => line_ending,
+  };
+  let writer = LineEndingWriter::new(line_ending, writer);
+  let mut sink = sink_for_format(format, reader, writer);
+  for feed in feeds {
+    sink.write_feed(feed)?;
+  }
+
+  sink.finish()
+}
+
+/// Writes one feed URL per line.
+pub struct UrlSink<W: Write>(pub W);
+
+impl<W: Write> FeedSink for UrlSink<W> {
+  fn write_feed(&mut self, feed: &Feed) -> Result<()> {
+    writeln!(self.0, "{}", feed.url)?;
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<()> {
+    self.0.flush()?;
+    Ok(())
+  }
+}
+
+/// Collects the feeds into an OPML document and writes it when finished.
+pub struct OpmlSink<W: Write> {
+  /// The document that feeds are added to.
+  document: opml::OPML,
+
+  /// Where the finished document is written to.
+  writer: W,
+}
+
+impl<W: Write> OpmlSink<W> {
+  /// Creates a new sink with an empty OPML document.
+  pub fn new(writer: W) -> Self {
+    Self {
+      document: opml::OPML {
+        head: None,
+        ..Default::default()
+      },
+      writer,
+    }
+  }
+}
+
+impl<W: Write> FeedSink for OpmlSink<W> {
+  fn write_feed(&mut self, feed: &Feed) -> Result<()> {
+    let text = feed.text.as_deref().unwrap_or(&feed.url);
+    let Some(group) = &feed.group else {
+      self.document.add_feed(text, &feed.url);
+      return Ok(());
+    };
+
+    let outlines = &mut self.document.body.outlines;
+    let folder = outlines
+      .iter()
+      .position(|outline| outline.xml_url.is_none() && outline.text == *group);
+    let folder = match folder {
+      Some(index) => &mut outlines[index],
+      None => {
+        outlines.push(opml::Outline {
+          text: group.clone(),
+          ..Default::default()
+        });
+        outlines.last_mut().unwrap()
+      }
+    };
+
+    folder.add_feed(text, &feed.url);
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<()> {
+    writeln!(self.writer, "{}", self.document.to_string()?)?;
+    self.writer.flush()?;
+    Ok(())
+  }
+}
+
+/// Collects the feeds and writes them as a single JSON array when finished.
+pub struct JsonSink<W: Write> {
+  /// The feeds written so far.
+  feeds: Vec<Value>,
+
+  /// Where the finished array is written to.
+  writer: W,
+}
+
+impl<W: Write> JsonSink<W> {
+  /// Creates a new sink without any feeds.
+  pub fn new(writer: W) -> Self {
+    Self {
+      feeds: vec![],
+      writer,
+    }
+  }
+}
+
+impl<W: Write> FeedSink for JsonSink<W> {
+  fn write_feed(&mut self, feed: &Feed) -> Result<()> {
+    self.feeds.push(serde_json::to_value(feed)?);
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<()> {
+    writeln!(
+      self.writer,
+      "{}",
+      serde_json::to_string_pretty(&self.feeds)?
+    )?;
+    self.writer.flush()?;
+    Ok(())
+  }
+}
+
+/// Writes every feed as a JSON object on its own line.
+pub struct NdjsonSink<W: Write>(pub W);
+
+impl<W: Write> FeedSink for NdjsonSink<W> {
+  fn write_feed(&mut self, feed: &Feed) -> Result<()> {
+    writeln!(self.0, "{}", serde_json::to_string(feed)?)?;
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<()> {
+    self.0.flush()?;
+    Ok(())
+  }
+}
+
+/// Writes the feeds as CSV with a header row.
+pub struct CsvSink<W: Write> {
+  /// Whether the header row has been written yet.
+  wrote_header: bool,
+
+  /// Where the rows are written to.
+  writer: W,
+}
+
+impl<W: Write> CsvSink<W> {
+  /// The columns of every row, in order.
+  const HEADER: [&'static str; 3] = ["url", "text", "friendly_url"];
+
+  /// Creates a new sink that hasn't written anything yet.
+  pub fn new(writer: W) -> Self {
+    Self {
+      wrote_header: false,
+      writer,
+    }
+  }
+
+  /// Writes a single row, quoting any fields that need it.
+  fn write_row(&mut self, fields: &[&str]) -> Result<()> {
+    let row = fields
+      .iter()
+      .map(|field| csv_escape(field))
+      .collect::<Vec<_>>()
+      .join(",");
+    writeln!(self.writer, "{row}")?;
+    Ok(())
+  }
+}
+
+impl<W: Write> FeedSink for CsvSink<W> {
+  fn write_feed(&mut self, feed: &Feed) -> Result<()> {
+    if !self.wrote_header {
+      self.write_row(&Self::HEADER)?;
+      self.wrote_header = true;
+    }
+
+    self.write_row(&[
+      &feed.url,
+      feed.text.as_deref().unwrap_or_default(),
+      feed.friendly_url.as_deref().unwrap_or_default(),
+    ])
+  }
+
+  fn finish(&mut self) -> Result<()> {
+    self.writer.flush()?;
+    Ok(())
+  }
+}
+
+/// Quotes a CSV field when it contains a comma, quote or newline.
+fn csv_escape(field: &str) -> String {
+  if field.contains([',', '"', '\n', '\r']) {
+    format!("\"{}\"", field.replace('"', "\"\""))
+  } else {
+    field.to_string()
+  }
+}
+
+/// Writes an HTML page with a list of links to the feeds.
+pub struct HtmlSink<W: Write> {
+  /// Whether the start of the page has been written yet.
+  wrote_header: bool,
+
+  /// Where the page is written to.
+  writer: W,
+}
+
+impl<W: Write> HtmlSink<W> {
+  /// Creates a new sink that hasn't written anything yet.
+  pub fn new(writer: W) -> Self {
+    Self {
+      wrote_header: false,
+      writer,
+    }
+  }
+
+  /// Writes the start of the page if that hasn't happened yet.
+  fn write_header(&mut self) -> Result<()> {
+    if !self.wrote_header {
+      writeln!(
+        self.writer,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Steam RSS Feeds</title>\n</head>\n<body>\n<ul>"
+      )?;
+      self.wrote_header = true;
+    }
+
+    Ok(())
+  }
+}
+
+impl<W: Write> FeedSink for HtmlSink<W> {
+  fn write_feed(&mut self, feed: &Feed) -> Result<()> {
+    self.write_header()?;
+
+    let url = html_escape(&feed.url);
+    let text = html_escape(feed.text.as_deref().unwrap_or(&feed.url));
+    let icon = feed.icon.as_deref().map_or_else(String::new, |icon| {
+      format!("<img src=\"{}\" alt=\"\"> ", html_escape(icon))
+    });
+    writeln!(self.writer, "<li>{icon}<a href=\"{url}\">{text}</a></li>")?;
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<()> {
+    self.write_header()?;
+    writeln!(self.writer, "</ul>\n</body>\n</html>")?;
+    self.writer.flush()?;
+    Ok(())
+  }
+}
+
+/// Escapes the characters that have special meaning in HTML.
+fn html_escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&#39;")
+}
+
+/// Writes a shell script that adds every feed to a feed reader.
+///
+/// Only the first feed for every AppID is added, so the same game found in
+/// multiple users' libraries doesn't make the script fail halfway through on
+/// a name that's already taken.
+pub struct ScriptSink<W: Write> {
+  /// The reader to add the feeds to.
+  reader: Reader,
+
+  /// The AppIDs of the feeds written so far.
+  written_appids: HashSet<usize>,
+
+  /// Whether the shebang has been written yet.
+  wrote_shebang: bool,
+
+  /// Where the script is written to.
+  writer: W,
+}
+
+impl<W: Write> ScriptSink<W> {
+  /// Creates a new sink for `reader` that hasn't written anything yet.
+  pub fn new(reader: Reader, writer: W) -> Self {
+    Self {
+      reader,
+      written_appids: HashSet::new(),
+      wrote_shebang: false,
+      writer,
+    }
+  }
+}
+
+impl<W: Write> FeedSink for ScriptSink<W> {
+  fn write_feed(&mut self, feed: &Feed) -> Result<()> {
+    if !self.wrote_shebang {
+      writeln!(self.writer, "#!/bin/sh\nset -e\n")?;
+      self.wrote_shebang = true;
+    }
+
+    if !self.written_appids.insert(feed.appid) {
+      return Ok(());
+    }
+
+    let url = shell_quote(&feed.url);
+    let text = feed.text.as_deref().unwrap_or(&feed.url);
+    match self.reader {
+      Reader::Canto => writeln!(self.writer, "canto-remote addfeed {url}")?,
+      Reader::Newsboat => {
+        let text = text.replace('"', "\\\"");
+        let line = shell_quote(&format!("{} \"~{text}\"", feed.url));
+        writeln!(self.writer, "echo {line} >> \"$HOME/.newsboat/urls\"")?;
+      }
+      Reader::Rss2email => {
+        writeln!(self.writer, "r2e add steam-{} {url}", feed.appid)?;
+      }
+    }
+
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<()> {
+    self.writer.flush()?;
+    Ok(())
+  }
+}
+
+/// Quotes a string so a POSIX shell treats it as a single literal word.
+fn shell_quote(text: &str) -> String {
+  format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// A writer that converts every `\n` it receives to the chosen
+/// [`LineEnding`] before passing it on.
+pub struct LineEndingWriter<W: Write> {
+  /// The line ending to write.
+  line_ending: LineEnding,
+
+  /// Whether the last byte written was a `\r`, so `\r\n` split across two
+  /// writes doesn't get an extra `\r`.
+  last_was_cr: bool,
+
+  /// The underlying writer.
+  writer: W,
+}
+
+impl<W: Write> LineEndingWriter<W> {
+  /// Creates a new writer that uses `line_ending`.
+  pub fn new(line_ending: LineEnding, writer: W) -> Self {
+    Self {
+      line_ending,
+      last_was_cr: false,
+      writer,
+    }
+  }
+}
+
+impl<W: Write> Write for LineEndingWriter<W> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    if self.line_ending == LineEnding::Lf {
+      return self.writer.write(buf);
+    }
+
+    let mut converted = Vec::with_capacity(buf.len());
+    for &byte in buf {
+      if byte == b'\n' && !self.last_was_cr {
+        converted.push(b'\r');
+      }
+
+      converted.push(byte);
+      self.last_was_cr = byte == b'\r';
+    }
+
+    self.writer.write_all(&converted)?;
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.writer.flush()
+  }
+}
+
+/// Parses generated OPML again for `--validate-opml`, and checks that every
+/// feed is still in it with the same text and URL.
+pub fn validate_opml(opml: &str, feeds: &[Feed]) -> Result<()> {
+  let document = opml::OPML::from_str(opml)
+    .wrap_err("The generated OPML couldn't be parsed (--validate-opml)")?;
+  let mut outlines = vec![];
+  collect_feed_outlines(&document.body.outlines, &mut outlines);
+
+  let mut parsed = outlines
+    .into_iter()
+    .map(|outline| (outline.text.as_str(), outline.xml_url.as_deref()))
+    .collect::<Vec<_>>();
+  let mut expected = feeds
+    .iter()
+    .map(|feed| {
+      let text = feed.text.as_deref().unwrap_or(&feed.url);
+      (text, Some(feed.url.as_str()))
+    })
+    .collect::<Vec<_>>();
+  parsed.sort_unstable();
+  expected.sort_unstable();
+
+  if let Some((text, _)) = expected.iter().find(|feed| !parsed.contains(feed)) {
+    bail!("The generated OPML doesn't contain \"{text}\" (--validate-opml)");
+  }
+
+  if parsed != expected {
+    bail!("The generated OPML contains extra feeds (--validate-opml)");
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use {super::*, crate::fixtures::feed};
+
+  /// Writes every chunk to a [`LineEndingWriter`] separately and returns the
+  /// result.
+  fn convert(line_ending: LineEnding, chunks: &[&str]) -> String {
+    let mut output = vec![];
+    let mut writer = LineEndingWriter::new(line_ending, &mut output);
+    for chunk in chunks {
+      writer.write_all(chunk.as_bytes()).unwrap();
+    }
+
+    String::from_utf8(output).unwrap()
+  }
+
+  /// Generates the OPML output for `feeds`.
+  fn opml(feeds: &[Feed]) -> String {
+    let mut output = vec![];
+    write_feeds(
+      Format::Opml,
+      Reader::Newsboat,
+      LineEnding::Lf,
+      &mut output,
+      feeds,
+    )
+    .unwrap();
+    String::from_utf8(output).unwrap()
+  }
+
+  #[test]
+  fn line_endings_are_converted() {
+    assert_eq!(convert(LineEnding::Lf, &["a\nb\r\n"]), "a\nb\r\n");
+    assert_eq!(convert(LineEnding::Crlf, &["a\nb\n"]), "a\r\nb\r\n");
+    assert_eq!(convert(LineEnding::Crlf, &["a\r\nb"]), "a\r\nb");
+  }
+
+  #[test]
+  fn crlf_split_across_writes_isnt_doubled() {
+    assert_eq!(convert(LineEnding::Crlf, &["a\r", "\nb"]), "a\r\nb");
+    assert_eq!(convert(LineEnding::Crlf, &["a\r", "b\n"]), "a\rb\r\n");
+  }
+
+  #[test]
+  fn crlf_output_is_written_when_requested() {
+    let feeds = [feed(440, "TF2"), feed(570, "Dota 2")];
+    let mut output = vec![];
+    write_feeds(
+      Format::Urls,
+      Reader::Newsboat,
+      LineEnding::Crlf,
+      &mut output,
+      &feeds,
+    )
+    .unwrap();
+    assert_eq!(
+      String::from_utf8(output).unwrap(),
+      "https://steamcommunity.com/games/440/rss/\r\n\
+       https://steamcommunity.com/games/570/rss/\r\n"
+    );
+  }
+
+  #[test]
+  fn csv_fields_are_quoted_when_needed() {
+    assert_eq!(csv_escape("plain"), "plain");
+    assert_eq!(csv_escape("a,b"), "\"a,b\"");
+    assert_eq!(csv_escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+    assert_eq!(csv_escape("two\nlines"), "\"two\nlines\"");
+  }
+
+  #[test]
+  fn shell_words_are_quoted() {
+    assert_eq!(shell_quote("plain"), "'plain'");
+    assert_eq!(shell_quote("it's $HOME"), r"'it'\''s $HOME'");
+  }
+
+  #[test]
+  fn scripts_skip_repeated_appids() {
+    let mut output = vec![];
+    let mut sink = ScriptSink::new(Reader::Rss2email, &mut output);
+    for feed in [feed(440, "TF2"), feed(440, "TF2"), feed(570, "Dota 2")] {
+      sink.write_feed(&feed).unwrap();
+    }
+    sink.finish().unwrap();
+
+    let script = String::from_utf8(output).unwrap();
+    assert_eq!(script.matches("r2e add steam-440 ").count(), 1);
+    assert_eq!(script.matches("r2e add steam-570 ").count(), 1);
+  }
+
+  #[test]
+  fn html_is_escaped() {
+    assert_eq!(
+      html_escape(r#"<a href="x">Tom & Jerry's</a>"#),
+      "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+    );
+  }
+
+  #[test]
+  fn html_includes_icons() {
+    let mut with_icon = feed(440, "TF2");
+    with_icon.icon = Some("https://cdn.example/440.jpg?a&b".to_string());
+
+    let mut output = vec![];
+    let mut sink = HtmlSink::new(&mut output);
+    sink.write_feed(&with_icon).unwrap();
+    sink.write_feed(&feed(570, "Dota 2")).unwrap();
+    sink.finish().unwrap();
+
+    let html = String::from_utf8(output).unwrap();
+    assert!(html.contains(
+      "<li><img src=\"https://cdn.example/440.jpg?a&amp;b\" alt=\"\"> \
+       <a href=\"https://steamcommunity.com/games/440/rss/\">TF2</a></li>"
+    ));
+    assert!(html.contains(
+      "<li><a href=\"https://steamcommunity.com/games/570/rss/\">Dota 2</a></li>"
+    ));
+    assert_eq!(html.matches("<img").count(), 1);
+  }
+
+  #[test]
+  fn opml_groups_feeds_into_folders() {
+    let mut first = feed(440, "TF2");
+    first.group = Some("Steam — Bauke".to_string());
+    let mut second = feed(570, "Dota 2");
+    second.group = first.group.clone();
+    let ungrouped = feed(10, "Counter-Strike");
+
+    let mut sink = OpmlSink::new(vec![]);
+    for feed in [&first, &ungrouped, &second] {
+      sink.write_feed(feed).unwrap();
+    }
+
+    let outlines = &sink.document.body.outlines;
+    assert_eq!(outlines.len(), 2);
+    assert_eq!(outlines[0].text, "Steam — Bauke");
+    assert_eq!(outlines[0].xml_url, None);
+    let folder = outlines[0]
+      .outlines
+      .iter()
+      .map(|outline| outline.text.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(folder, ["TF2", "Dota 2"]);
+    assert_eq!(outlines[1].text, "Counter-Strike");
+    assert_eq!(outlines[1].xml_url, Some(ungrouped.url));
+  }
+
+  #[test]
+  fn generated_opml_with_escaped_titles_validates() {
+    let feeds = [
+      feed(440, r#"Tom & Jerry's <"Beta">"#),
+      feed(570, "Dota 2 — ünïcödé"),
+    ];
+    validate_opml(&opml(&feeds), &feeds).unwrap();
+  }
+
+  #[test]
+  fn broken_opml_fails_validation() {
+    let feeds = [feed(440, "Control \u{1} character")];
+    assert!(validate_opml(&opml(&feeds), &feeds).is_err());
+
+    let missing = [feed(440, "TF2"), feed(570, "Dota 2")];
+    let error = validate_opml(&opml(&missing[..1]), &missing).unwrap_err();
+    assert!(error.to_string().contains("Dota 2"), "{error}");
+
+    let extra = [feed(440, "TF2")];
+    assert!(validate_opml(&opml(&missing), &extra).is_err());
+  }
+}
@@ -0,0 +1,418 @@
+//! Steam's URLs and the parts of its pages that are scraped.
+
+use std::{borrow::Cow, sync::LazyLock};
+
+use {
+  color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+  },
+  regex::Regex,
+  serde::Deserialize,
+  serde_json::Value,
+};
+
+use crate::{
+  date,
+  http::{Client, StatusError},
+};
+
+/// Matches a Steam store URL and captures its AppID.
+pub(crate) static STORE_URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+  Regex::new(r"(?i)^https?://store.steampowered.com/app/(?P<appid>\d+)")
+    .unwrap()
+});
+
+/// Matches the BBCode tags Steam uses. Only Steam's own tags are matched, so
+/// names like `Foo [Beta]` stay intact.
+static BBCODE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+  Regex::new(
+    r"\[/?(?:b|i|u|s|strike|h1|h2|h3|url|img|list|olist|\*|quote|code|spoiler|noparse|hr|table|tr|th|td)(?:=[^\]]*)?\]",
+  )
+  .unwrap()
+});
+
+/// Matches the JSON with a user's games on their games page.
+static USER_JSON_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+  Regex::new(r"var rgGames = (?P<json>\[.+\]);\s+var").unwrap()
+});
+
+/// Matches a user's display name on their games page.
+pub(crate) static USER_NAME_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+  Regex::new(r"<title>Steam Community :: (?P<name>.+) :: Games</title>")
+    .unwrap()
+});
+
+/// Matches a vanity User ID.
+pub(crate) static USER_ID_REGEX: LazyLock<Regex> =
+  LazyLock::new(|| Regex::new(r"(i?)^\w+$").unwrap());
+
+/// Matches a user's SteamID64 on their games page.
+pub(crate) static USER_STEAMID_REGEX: LazyLock<Regex> =
+  LazyLock::new(|| Regex::new(r#""steamid":"(?P<steamid>\d+)""#).unwrap());
+
+/// Matches the friendly URL name in the links on a game's community hub.
+pub(crate) static HUB_FRIENDLY_URL_REGEX: LazyLock<Regex> =
+  LazyLock::new(|| {
+    Regex::new(r"(?i)steamcommunity\.com/games/(?P<name>[^/?#]+)/announcements")
+      .unwrap()
+  });
+
+/// Matches a steamcommunity.com profile URL and captures either its vanity
+/// User ID or its SteamID64.
+pub(crate) static USER_URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+  Regex::new(
+    r"(?i)^(?:https?://)?(?:www\.)?steamcommunity\.com/(?:id/(?P<userid>\w+)|profiles/(?P<steamid>\d+))/?",
+  )
+  .unwrap()
+});
+
+/// A small representation of a Steam game that is parsed from JSON.
+///
+/// Any other fields Steam includes are ignored, so new ones can't break
+/// parsing. Only `appid` and `name` are required.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SteamApp {
+  /// The AppID of the game.
+  pub appid: usize,
+
+  /// The name of the game.
+  pub name: String,
+
+  /// A friendly URL name of the game, some feeds will use this instead of their
+  /// AppID for their RSS feed.
+  ///
+  /// For example, [Portal's feed](https://steamcommunity.com/games/Portal/rss)
+  /// uses `Portal`, instead of
+  /// [its AppID 400](https://steamcommunity.com/games/400/rss).
+  ///
+  /// Some games may also have a friendly URL different from their AppID but
+  /// don't use it for their feed. Steam is weird.
+  #[serde(default, rename = "friendlyURL")]
+  pub friendly_url: Value,
+
+  /// The URL of the game's logo.
+  #[serde(default)]
+  pub logo: Option<String>,
+}
+
+/// Checks whether a `--user` could be both a vanity User ID and a SteamID64,
+/// which are 17 digits that start with `7656119`.
+pub fn is_ambiguous_user(user: &str) -> bool {
+  user.len() == 17
+    && user.starts_with("7656119")
+    && user.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Parses the games on a user's games page. Returns `None` when the page
+/// doesn't list any games, which happens when the user's game details are
+/// private.
+pub fn parse_games(body: &str) -> Option<Result<Vec<SteamApp>>> {
+  let games_json = USER_JSON_REGEX
+    .captures(body)
+    .and_then(|captures| captures.name("json"))?;
+  Some(serde_json::from_str(games_json.as_str()).map_err(Into::into))
+}
+
+/// A Steam user, identified in one of the two ways their profile URLs can.
+#[derive(Debug, Eq, PartialEq)]
+pub enum UserRef {
+  /// A vanity User ID, as used in `/id/` URLs.
+  UserId(String),
+
+  /// A SteamID64, as used in `/profiles/` URLs.
+  SteamId(String),
+}
+
+impl UserRef {
+  /// Creates the URL of the user's Steam Games page.
+  pub fn games_url(&self) -> String {
+    match self {
+      Self::UserId(userid) => userid_to_games_url(userid),
+      Self::SteamId(steamid) => steamid_to_games_url(steamid),
+    }
+  }
+}
+
+/// Parses a `--user` input, which is either a bare User ID or a
+/// steamcommunity.com profile URL. URLs can use `http` or `https`, a `www.`
+/// subdomain and a trailing slash. Ambiguous inputs that could also be a
+/// SteamID64 are parsed as User IDs, see [`is_ambiguous_user`].
+pub fn parse_user(user: &str) -> Option<UserRef> {
+  let user = user.trim();
+  if USER_ID_REGEX.is_match(user) {
+    return Some(UserRef::UserId(user.to_string()));
+  }
+
+  let captures = USER_URL_REGEX.captures(user)?;
+  if let Some(userid) = captures.name("userid") {
+    Some(UserRef::UserId(userid.as_str().to_string()))
+  } else {
+    captures
+      .name("steamid")
+      .map(|steamid| UserRef::SteamId(steamid.as_str().to_string()))
+  }
+}
+
+/// Returns the most recent `pubDate` of the items in a feed.
+pub fn latest_item_date(body: &str) -> Option<i64> {
+  body
+    .split("<pubDate>")
+    .skip(1)
+    .filter_map(|rest| rest.split_once("</pubDate>"))
+    .filter_map(|(date, _)| date::parse_rfc2822(date.trim()))
+    .max()
+}
+
+/// Checks whether a feed has an item from within the last `days` before
+/// `now`, for `--active-within`.
+pub fn is_active(body: &str, days: u64, now: i64) -> bool {
+  let window = i64::try_from(days)
+    .unwrap_or(i64::MAX)
+    .saturating_mul(86400);
+  let cutoff = now.saturating_sub(window);
+  latest_item_date(body).is_some_and(|latest| latest >= cutoff)
+}
+
+/// Returns the text between the first opening and closing `tag` in `body`.
+pub fn find_element_text<'b>(body: &'b str, tag: &str) -> Option<&'b str> {
+  let start = body.find(&format!("<{tag}>"))? + tag.len() + 2;
+  let end = start + body[start..].find(&format!("</{tag}>"))?;
+  Some(body[start..end].trim())
+}
+
+/// Checks whether a feed title belongs to the game with `name`, ignoring
+/// case and Steam's additions to the title.
+pub fn titles_match(title: &str, name: &str) -> bool {
+  title.to_lowercase().contains(&name.trim().to_lowercase())
+}
+
+/// Removes Steam's BBCode tags from `text` for `--strip-bbcode`, keeping the
+/// text inside of them.
+pub fn strip_bbcode(text: &str) -> Cow<'_, str> {
+  BBCODE_REGEX.replace_all(text, "")
+}
+
+/// Replaces the basic HTML entities in `text` with the characters they
+/// represent.
+pub fn unescape_html(text: &str) -> String {
+  text
+    .replace("&lt;", "<")
+    .replace("&gt;", ">")
+    .replace("&quot;", "\"")
+    .replace("&#39;", "'")
+    .replace("&amp;", "&")
+}
+
+/// Checks whether a response body is Steam's age check page.
+pub fn is_age_gate(body: &str) -> bool {
+  body.contains("id=\"app_agegate\"") || body.contains("/agecheckset/")
+}
+
+/// Creates a Steam RSS URL from a given AppID.
+pub fn appid_to_rss_url<D: std::fmt::Display>(appid: D) -> String {
+  format!("https://steamcommunity.com/games/{appid}/rss/")
+}
+
+/// Creates a Steam Store news RSS URL from a given AppID.
+pub fn appid_to_store_rss_url<D: std::fmt::Display>(appid: D) -> String {
+  format!("https://store.steampowered.com/feeds/news/app/{appid}/")
+}
+
+/// Creates a Steam Community hub URL from a given AppID.
+pub fn appid_to_hub_url<D: std::fmt::Display>(appid: D) -> String {
+  format!("https://steamcommunity.com/app/{appid}")
+}
+
+/// Creates a Steam Store app details API URL from a given AppID.
+pub fn appid_to_details_url<D: std::fmt::Display>(appid: D) -> String {
+  format!("https://store.steampowered.com/api/appdetails?appids={appid}&filters=basic")
+}
+
+/// Creates a user's Steam Games URL from a given User ID.
+pub fn userid_to_games_url<D: std::fmt::Display>(userid: D) -> String {
+  format!("https://steamcommunity.com/id/{userid}/games/?tab=all")
+}
+
+/// Creates a user's Steam Games URL from a given SteamID64.
+pub fn steamid_to_games_url<D: std::fmt::Display>(steamid: D) -> String {
+  format!("https://steamcommunity.com/profiles/{steamid}/games/?tab=all")
+}
+
+/// Creates a Steam Web API URL for an `Interface/Method/Version` with the API
+/// key and the rest of the `query`.
+pub fn steam_api_url(method: &str, api_key: &str, query: &str) -> String {
+  format!("https://api.steampowered.com/{method}/?key={api_key}&{query}")
+}
+
+/// Calls a Steam Web API method and parses the JSON it returns. The errors
+/// never include the URL, so the API key in it isn't printed.
+pub fn call_steam_api(
+  client: &Client,
+  method: &str,
+  api_key: &str,
+  query: &str,
+) -> Result<Value> {
+  let url = steam_api_url(method, api_key, query);
+  let response = client.get(&url).call().map_err(|error| {
+    match (
+      error.downcast_ref::<StatusError>(),
+      error.downcast_ref::<ureq::Transport>(),
+    ) {
+      (
+        Some(StatusError {
+          status: status @ (401 | 403),
+          ..
+        }),
+        _,
+      ) => eyre!(
+        "The Steam Web API refused {method} with HTTP {status}, the API key \
+         may be invalid or the profile private."
+      ),
+      (Some(StatusError { status, .. }), _) => {
+        eyre!("The Steam Web API returned HTTP {status} for {method}.")
+      }
+      (None, Some(transport)) => eyre!(
+        "Couldn't reach the Steam Web API for {method}: {}",
+        transport.kind()
+      ),
+      (None, None) => eyre!("Couldn't reach the Steam Web API for {method}."),
+    }
+  })?;
+
+  serde_json::from_str(&client.read_body(response)?)
+    .wrap_err_with(|| format!("Couldn't parse the response of {method}"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn users_are_parsed() {
+    let userid = Some(UserRef::UserId("Bauke".to_string()));
+    let steamid = Some(UserRef::SteamId("76561198000000000".to_string()));
+    for (input, expected) in [
+      ("Bauke", &userid),
+      (" Bauke\n", &userid),
+      ("https://steamcommunity.com/id/Bauke", &userid),
+      ("https://steamcommunity.com/id/Bauke/", &userid),
+      ("http://steamcommunity.com/id/Bauke/", &userid),
+      ("steamcommunity.com/id/Bauke", &userid),
+      ("https://www.steamcommunity.com/id/Bauke/", &userid),
+      ("HTTPS://WWW.STEAMCOMMUNITY.COM/id/Bauke/games/", &userid),
+      (
+        "https://steamcommunity.com/profiles/76561198000000000",
+        &steamid,
+      ),
+      (
+        "https://www.steamcommunity.com/profiles/76561198000000000/",
+        &steamid,
+      ),
+      (
+        "http://steamcommunity.com/profiles/76561198000000000/",
+        &steamid,
+      ),
+      ("https://example.com/id/Bauke/", &None),
+      ("https://steamcommunity.com/groups/steam-rss", &None),
+      ("https://steamcommunity.com/profiles/Bauke", &None),
+      ("", &None),
+    ] {
+      assert_eq!(&parse_user(input), expected, "{input:?}");
+    }
+  }
+
+  #[test]
+  fn element_text_is_found() {
+    let body = "<rss><channel><title> Team Fortress 2 </title>\
+                <item><title>Update</title></item></channel></rss>";
+    assert_eq!(find_element_text(body, "title"), Some("Team Fortress 2"));
+    assert_eq!(find_element_text(body, "lastBuildDate"), None);
+    assert_eq!(find_element_text("<title>Unclosed", "title"), None);
+  }
+
+  #[test]
+  fn bbcode_is_stripped() {
+    assert_eq!(
+      strip_bbcode("[b][url=https://example.com]Half-Life[/url] 3[/b]"),
+      "Half-Life 3"
+    );
+    assert_eq!(
+      strip_bbcode("[h1]Patch Notes[/h1][list][*]Fixes[/list]"),
+      "Patch NotesFixes"
+    );
+    assert_eq!(strip_bbcode("Foo [Beta]"), "Foo [Beta]");
+    assert!(matches!(strip_bbcode("Dota 2"), Cow::Borrowed("Dota 2")));
+  }
+
+  #[test]
+  fn games_are_parsed_with_unknown_fields() -> Result<()> {
+    let body = concat!(
+      r#"var rgGames = [{"appid":440,"name":"Team Fortress 2","#,
+      r#""friendlyURL":"tf2","logo":"l.jpg","hours_forever":"1,234","#,
+      r#""availStatLinks":{"achievements":true}},"#,
+      r#"{"appid":400,"name":"Portal","friendlyURL":false},"#,
+      r#"{"appid":570,"name":"Dota 2","newSteamField":[1,2,3]}];"#,
+      "\n var rgChangingGames = [];",
+    );
+    let games = parse_games(body).unwrap()?;
+
+    assert_eq!(
+      games
+        .iter()
+        .map(|game| (game.appid, game.name.as_str()))
+        .collect::<Vec<_>>(),
+      [(440, "Team Fortress 2"), (400, "Portal"), (570, "Dota 2")]
+    );
+    assert_eq!(games[0].friendly_url, "tf2");
+    assert_eq!(games[0].logo.as_deref(), Some("l.jpg"));
+    assert!(games[1].friendly_url.is_boolean());
+    assert!(games[2].friendly_url.is_null());
+    assert!(games[2].logo.is_none());
+    Ok(())
+  }
+
+  #[test]
+  fn games_missing_required_fields_fail_to_parse() {
+    for games in [r#"[{"name":"No AppID"}]"#, r#"[{"appid":440}]"#] {
+      let body = format!("var rgGames = {games};\n var rgChangingGames = [];");
+      let error = parse_games(&body).unwrap().unwrap_err().to_string();
+      assert!(error.starts_with("missing field"), "{error}");
+    }
+
+    assert!(parse_games("<html>Private</html>").is_none());
+  }
+
+  #[test]
+  fn ambiguous_users_are_detected() {
+    assert!(is_ambiguous_user("76561197960287930"));
+    assert!(!is_ambiguous_user("7656119796028793"));
+    assert!(!is_ambiguous_user("12345678901234567"));
+    assert!(!is_ambiguous_user("7656119796028793a"));
+    assert!(!is_ambiguous_user("Bauke"));
+  }
+
+  #[test]
+  fn latest_item_date_is_the_newest() {
+    let body =
+      "<item><pubDate>Mon, 09 Oct 2023 10:00:00 +0000</pubDate></item>\
+       <item><pubDate>Tue, 10 Oct 2023 17:12:03 +0000</pubDate></item>\
+       <item><pubDate>Not a date</pubDate></item>";
+    assert_eq!(latest_item_date(body), Some(1_696_957_923));
+    assert_eq!(latest_item_date("<rss></rss>"), None);
+  }
+
+  #[test]
+  fn feeds_are_active_within_the_window() {
+    let body =
+      "<item><pubDate>Tue, 10 Oct 2023 17:12:03 +0000</pubDate></item>";
+    let published = 1_696_957_923;
+
+    assert!(is_active(body, 7, published + 7 * 86400));
+    assert!(!is_active(body, 7, published + 7 * 86400 + 1));
+    assert!(is_active(body, 0, published));
+    assert!(is_active(body, u64::MAX, published + 86400));
+    assert!(!is_active("<rss></rss>", u64::MAX, published));
+  }
+}
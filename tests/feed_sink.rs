@@ -0,0 +1,93 @@
+//! Tests for implementing and using [`FeedSink`] from outside the crate.
+
+use {
+  clap::Parser,
+  color_eyre::Result,
+  steam_rss::{
+    http::{Request, Response},
+    resolve, sink_for_format, Args, Client, Feed, FeedSink, Format, Reader,
+  },
+};
+
+/// A sink that keeps the feed URLs and texts in memory.
+#[derive(Default)]
+struct MemorySink {
+  /// The URLs and texts of the feeds written so far.
+  feeds: Vec<(String, Option<String>)>,
+
+  /// Whether [`FeedSink::finish`] has been called.
+  finished: bool,
+}
+
+impl FeedSink for MemorySink {
+  fn write_feed(&mut self, feed: &Feed) -> Result<()> {
+    self.feeds.push((feed.url.clone(), feed.text.clone()));
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<()> {
+    self.finished = true;
+    Ok(())
+  }
+}
+
+/// Answers every feed request with XML, except for AppID 10 which returns
+/// an HTML page like invalid feeds do.
+fn transport(request: &Request) -> Result<Response> {
+  Ok(if request.url.contains("/10/") {
+    Response::new(&request.url, 200, "text/html", "<html></html>")
+  } else {
+    let appid = request.url.split('/').nth(4).unwrap_or_default();
+    let body =
+      format!("<rss><channel><title>Game {appid}</title></channel></rss>");
+    Response::new(&request.url, 200, "text/xml", body)
+  })
+}
+
+#[test]
+fn custom_sink_receives_every_resolved_feed() -> Result<()> {
+  let args = Args::parse_from(
+    "steam-rss --verify --timeout 0 --appid 440 --appid 10 --appid 570"
+      .split(' '),
+  );
+  let client = Client::new(&args, transport)?;
+
+  let mut sink = MemorySink::default();
+  let summary = resolve(&args, &client, &mut sink)?;
+
+  assert_eq!(
+    sink.feeds,
+    [
+      (
+        "https://steamcommunity.com/games/440/rss/".to_string(),
+        Some("Game 440".to_string())
+      ),
+      (
+        "https://steamcommunity.com/games/570/rss/".to_string(),
+        Some("Game 570".to_string())
+      ),
+    ]
+  );
+  assert!(sink.finished);
+  assert_eq!((summary.verified, summary.valid), (3, 2));
+  Ok(())
+}
+
+#[test]
+fn built_in_sink_writes_to_any_writer() -> Result<()> {
+  let mut output = vec![];
+  {
+    let mut sink = sink_for_format(Format::Urls, Reader::Newsboat, &mut output);
+    sink.write_feed(&Feed::new(
+      440,
+      "https://steamcommunity.com/games/440/rss/".to_string(),
+    ))?;
+    sink.finish()?;
+  }
+
+  assert_eq!(
+    String::from_utf8(output)?,
+    "https://steamcommunity.com/games/440/rss/\n"
+  );
+  Ok(())
+}